@@ -1,21 +1,59 @@
+use super::callgraph::CallGraph;
+use super::cfg;
+use super::diagnostic::Diagnostic;
+use super::disasm;
+use super::error::{self, Error, Result};
+use super::expr::{self, Env, Expr};
+use super::header::Header;
+use super::html;
+use super::model::Analysis;
 use super::parser;
-use std::collections::{HashMap, HashSet};
-use std::path::Path;
+use super::project::{Format, Graph};
+use super::symbol::{Location, SymbolTable};
+use super::variable::opcode;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::env;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Write;
 
-type AbsPath = String;
+type AbsPath = PathBuf;
 type RelPath = String;
 
 pub struct Analyzer {
     asm: HashMap<RelPath, parser::Nodes>, // アセンブリファイルの解析結果が入る
     text: HashSet<RelPath>,               // テキストファイルの相対パスを格納する
     remaining: Vec<AbsPath>,              // 解析対象のファイルの絶対パス一覧
-    macros: HashSet<String>,
+    macros: parser::Macros,
     cur_dir: AbsPath,
     base_dir: AbsPath,
     file_name: String,
+    entries: Vec<String>,
+    header: Option<Header>,     // ROMファイルのヘッダ
+    workspace: Option<AbsPath>, // 逆アセンブルしたソースを書き出すディレクトリ
+    temp_workspace: bool,       // workspaceを自分で作った一時ディレクトリなら捨てるときに消す
+    symbols: SymbolTable,
+    diagnostics: Vec<Diagnostic>,
+    sections: Vec<parser::SectionDirective>, // asmファイルのSECTION
+    cfg: cfg::Cfg,                           // 解析したファイル全体の制御フローグラフ
+}
+
+impl Drop for Analyzer {
+    fn drop(&mut self) {
+        // 自分で作った一時ディレクトリだけ消す
+        if let (true, Some(dir)) = (self.temp_workspace, &self.workspace) {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        return Self::new();
+    }
 }
 
 impl Analyzer {
@@ -24,74 +62,269 @@ impl Analyzer {
             asm: HashMap::new(),
             text: HashSet::new(),
             remaining: Default::default(),
-            macros: HashSet::new(),
+            macros: HashMap::new(),
             cur_dir: Default::default(),
             base_dir: Default::default(),
             file_name: Default::default(),
+            entries: Default::default(),
+            header: None,
+            workspace: None,
+            temp_workspace: false,
+            symbols: Default::default(),
+            diagnostics: vec![],
+            sections: vec![],
+            cfg: Default::default(),
         };
     }
 
-    pub fn init_analyze(&mut self, abs_file_path_list: Vec<String>) {
+    pub fn init_analyze(&mut self, abs_file_path_list: Vec<PathBuf>) -> Result<()> {
         for abs_file_path in abs_file_path_list {
             // すでに解析済みのasmファイルであるならskipする
-            if self.base_dir != "" {
-                let rel_file_path = self.to_relative(&abs_file_path);
-                match self.asm.get(&rel_file_path) {
-                    Some(_) => {
-                        continue;
-                    }
-                    _ => {}
+            if !self.base_dir.as_os_str().is_empty() {
+                let rel_file_path = self.to_relative(&abs_file_path)?;
+                if self.asm.contains_key(&rel_file_path) {
+                    continue;
                 }
             }
-            self.analyze(abs_file_path);
+            self.analyze(abs_file_path)?;
         }
+        self.build_cfg();
+        Ok(())
     }
 
-    fn analyze(&mut self, abs_file_path: String) {
+    fn analyze(&mut self, abs_file_path: PathBuf) -> Result<()> {
         if !is_asm(&abs_file_path) {
-            println!("invalid format.");
-            return;
+            return Err(Error::invalid_path(&abs_file_path, "is not an asm file"));
         }
 
         // base_dirはabs_file_pathを起点とした相対パスを得るのに必要
-        let path = Path::new(&abs_file_path);
-        self.base_dir = path.parent().unwrap().to_str().unwrap().to_string();
-        self.file_name = path.file_stem().unwrap().to_str().unwrap().to_string();
+        let (base_dir, file_name) = split_path(&abs_file_path)?;
+        self.base_dir = base_dir;
+        self.file_name = file_name;
 
         // 最初のファイルを解析
-        self.analyze_file(abs_file_path);
+        self.analyze_file(abs_file_path)?;
 
         // 2つ目以降のファイルを処理していく
         loop {
-            if self.remaining.len() == 0 {
+            if self.remaining.is_empty() {
                 break;
             }
 
             let next_file = self.remaining[0].clone();
             self.remaining.retain(|x| *x != next_file);
-            self.analyze_file(next_file);
+            self.analyze_file(next_file)?;
+        }
+
+        // 後ろで定義したシンボルを参照する定数を評価する
+        self.symbols.resolve();
+
+        self.check_labels();
+        Ok(())
+    }
+
+    /// 定義されていないラベルへのcall/jp/jrを警告する
+    ///
+    /// 全てのファイルを解析して、後ろで定義したラベルも分かってから調べる
+    fn check_labels(&mut self) {
+        let mut diagnostics = vec![];
+        for nodes in self.asm.values() {
+            for node in nodes.values() {
+                for element in &node.elements {
+                    // ソースのない逆アセンブルの命令は調べない
+                    let span = match element.span() {
+                        Some(span) => span,
+                        None => continue,
+                    };
+                    let target = match element.instruction().and_then(branch_target) {
+                        Some(target) => target,
+                        None => continue,
+                    };
+                    let name = if target.starts_with('.') {
+                        parser::remove_dash(&node.global) + target
+                    } else {
+                        target.to_string()
+                    };
+                    if !self.symbols.defined(&name) {
+                        let message = format!("undefined label {}", target);
+                        diagnostics.push(Diagnostic::warning(&message).at_span(span));
+                    }
+                }
+            }
+        }
+        diagnostics.sort_by(|a, b| (&a.file, a.line, a.column).cmp(&(&b.file, b.line, b.column)));
+        self.diagnostics.extend(diagnostics);
+    }
+
+    /// 解析中に見つかった問題をソースの行と一緒に整形する
+    ///
+    /// ROMでは逆アセンブルして`workspace`に書き出したソースの行を付ける
+    pub fn render_diagnostics(&self) -> Vec<String> {
+        let dir = match (&self.header, &self.workspace) {
+            (Some(_), Some(workspace)) => workspace,
+            _ => &self.base_dir,
+        };
+        let mut sources: HashMap<String, Vec<String>> = HashMap::new();
+        let mut rendered = vec![];
+        for diagnostic in &self.diagnostics {
+            let source = match (&diagnostic.file, diagnostic.line) {
+                (Some(file), Some(line)) => {
+                    let lines = sources.entry(file.clone()).or_insert_with(|| {
+                        let path = dir.join(file);
+                        let bytes = fs::read(path).unwrap_or_default();
+                        return String::from_utf8_lossy(&bytes)
+                            .lines()
+                            .map(|l| l.to_string())
+                            .collect();
+                    });
+                    lines.get(line - 1).cloned()
+                }
+                _ => None,
+            };
+            rendered.push(diagnostic.render(source.as_deref()));
+        }
+        return rendered;
+    }
+
+    /// rgbasmの`-D NAME=value`と同じように文字列のシンボルを定義する
+    ///
+    /// asmファイルを解析する前に呼ぶ
+    pub fn define(&mut self, name: &str, value: &str) -> Result<()> {
+        let location = Location {
+            file: "<command line>".to_string(),
+            line: None,
+        };
+        return self
+            .symbols
+            .define_string(name, value, location)
+            .map_err(Error::InvalidDefinition);
+    }
+
+    /// ROMファイルを逆アセンブルして解析結果に加える
+    ///
+    /// 見つかったエントリポイントは`entries`で取得できる
+    /// 逆アセンブルしたバンクごとのソースは`workspace`に書き出す
+    pub fn analyze_rom(&mut self, path: &Path) -> Result<()> {
+        let (base_dir, file_name) = split_path(path)?;
+        self.base_dir = base_dir;
+        self.file_name = file_name;
+
+        let rom = fs::read(path).map_err(|e| Error::io(path, e))?;
+        self.header = Header::parse(&rom);
+        if let Some(header) = &self.header {
+            for warning in &header.warnings {
+                self.diagnostics.push(Diagnostic::warning(warning));
+            }
         }
+        let disassembly = disasm::disassemble(&rom)?;
+        let workspace = match &self.workspace {
+            Some(dir) => dir.clone(),
+            None => {
+                let dir = temp_workspace();
+                self.workspace = Some(dir.clone());
+                self.temp_workspace = true;
+                dir
+            }
+        };
+        fs::create_dir_all(&workspace).map_err(|e| Error::io(&workspace, e))?;
+        let mut sources: Vec<_> = disassembly.sources.iter().collect();
+        sources.sort_by(|a, b| a.0.cmp(b.0));
+        for (filename, lines) in sources {
+            let path = workspace.join(filename);
+            fs::write(&path, lines.join("\n") + "\n").map_err(|e| Error::io(&path, e))?;
+        }
+        for (filename, nodes) in disassembly.files {
+            self.asm.insert(filename, nodes);
+        }
+        self.entries = disassembly.entries;
+        self.diagnostics.extend(disassembly.diagnostics);
+        self.build_cfg();
+        Ok(())
     }
 
-    pub fn analyze_file(&mut self, abs_file_path: String) {
-        println!("analyze {}...", self.to_relative(&abs_file_path));
+    /// 逆アセンブルしたソースを書き出すディレクトリを指定する
+    ///
+    /// 指定しなければ実行ごとに一時ディレクトリを作って書き出し、`Analyzer`を捨てるときに消す
+    pub fn set_workspace(&mut self, dir: PathBuf) {
+        self.workspace = Some(dir);
+        self.temp_workspace = false;
+    }
+
+    /// 逆アセンブルしたソースを書き出したディレクトリ
+    pub fn workspace(&self) -> Option<&Path> {
+        return self.workspace.as_deref();
+    }
+
+    /// ROMの逆アセンブルで見つかったエントリポイントのラベル
+    pub fn entries(&self) -> &[String] {
+        return &self.entries;
+    }
+
+    /// 解析したシンボル
+    pub fn symbols(&self) -> &SymbolTable {
+        return &self.symbols;
+    }
+
+    /// 解析中に見つかった問題
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        return &self.diagnostics;
+    }
+
+    /// asmファイルのSECTION
+    pub fn sections(&self) -> &[parser::SectionDirective] {
+        return &self.sections;
+    }
+
+    /// ラベルを置いたSECTION
+    pub fn section_of(&self, label: &str) -> Option<&parser::SectionDirective> {
+        let name = self
+            .asm
+            .values()
+            .find_map(|nodes| parser::get_node(nodes, label))?
+            .section
+            .as_ref()?;
+        return self.sections.iter().find(|s| &s.name == name);
+    }
+
+    /// ラベルを置いたバンク
+    ///
+    /// ROMでは逆アセンブルしたバンク、asmではSECTIONのバンク(ROMXでバンクを指定していなければNone)
+    pub fn bank_of(&self, label: &str) -> Option<usize> {
+        if self.header.is_some() {
+            let (file, _) = self
+                .asm
+                .iter()
+                .find(|(_, nodes)| parser::get_node(nodes, label).is_some())?;
+            return disasm::bank_of_file(file);
+        }
+        return self.section_of(label)?.bank();
+    }
+
+    pub fn analyze_file(&mut self, abs_file_path: PathBuf) -> Result<()> {
         // 拡張子を見てrednex asmファイルかどうかを判定し、処理を分岐させる
         if is_asm(&abs_file_path) {
-            self.analyze_asm_file(abs_file_path);
+            return self.analyze_asm_file(abs_file_path);
         } else {
-            self.analyze_text_file(abs_file_path);
+            return self.analyze_text_file(abs_file_path);
         }
     }
 
-    pub fn analyze_asm_file(&mut self, abs_file_path: String) {
+    pub fn analyze_asm_file(&mut self, abs_file_path: PathBuf) -> Result<()> {
         // 絶対パスを必要な形に加工する
-        let path = Path::new(&abs_file_path);
-        self.cur_dir = path.parent().unwrap().to_str().unwrap().to_string();
-        let filename = path.file_name().unwrap().to_str().unwrap().to_string();
+        let (cur_dir, _) = split_path(&abs_file_path)?;
+        self.cur_dir = cur_dir;
+        let filename = match abs_file_path.file_name() {
+            Some(name) => error::path_str(Path::new(name))?,
+            None => return Err(Error::invalid_path(&abs_file_path, "has no file name")),
+        };
 
         // 解析を行う
-        let mut p = parser::Parser::new(abs_file_path, self.base_dir.clone(), self.macros.clone());
+        let mut p = parser::Parser::new(&abs_file_path, &self.base_dir, self.macros.clone())?;
+        p.set_symbols(std::mem::take(&mut self.symbols));
         let (result, macros) = p.parse(filename);
+        self.symbols = p.take_symbols();
+        self.diagnostics.extend(p.take_diagnostics());
+        self.sections.extend(p.take_sections());
         // 解析結果を保存する
         for (filename, nodes) in result {
             self.asm.insert(filename, nodes);
@@ -99,207 +332,599 @@ impl Analyzer {
 
         // マクロテーブルを更新する
         self.macros = macros;
+        Ok(())
     }
 
-    pub fn analyze_text_file(&mut self, abs_file_path: String) {
+    pub fn analyze_text_file(&mut self, abs_file_path: PathBuf) -> Result<()> {
         // テキストファイルはそのテキストファイルの相対パスを保存しておく
-        let rel_file_path = self.to_relative(&abs_file_path);
+        let rel_file_path = self.to_relative(&abs_file_path)?;
         self.text.insert(rel_file_path);
         self.remaining.retain(|x| *x != abs_file_path);
+        Ok(())
     }
 
-    #[allow(dead_code)]
     /// 解析結果をjson形式で出力する
-    pub fn dump_json(&self, dir_path: &str) {
-        let path = Path::new(dir_path);
+    ///
+    /// 形式は`model::Analysis`で、`model::SCHEMA_VERSION`でバージョンを管理する
+    pub fn dump_json(&self, dir_path: &Path, init_labels: &[String]) -> Result<PathBuf> {
+        prepare_dir(dir_path)?;
 
-        if !path.exists() {
-            println!("target dir does not exist");
-            return;
-        }
+        let filename = dir_path.join(self.file_name.clone() + ".json");
+        return write_file(&filename, &self.analysis(init_labels).to_json()?);
+    }
 
-        if !path.is_dir() {
-            println!("target path is not a directory");
-            return;
+    /// json出力と同じ形の解析結果
+    pub fn analysis(&self, init_labels: &[String]) -> Analysis {
+        return Analysis::new(
+            &self.asm,
+            &self.cfg,
+            &self.text,
+            &self.header,
+            init_labels,
+            &self.symbols,
+            &self.diagnostics,
+            &self.sections,
+        );
+    }
+
+    /// グラフと形式を選んで`dir_path`に出力し、書き出したファイルのパスを返す
+    pub fn dump(
+        &self,
+        dir_path: &Path,
+        init_labels: &[String],
+        graph: Graph,
+        format: Format,
+    ) -> Result<PathBuf> {
+        match (graph, format) {
+            (Graph::Cfg, Format::Flowchart) => return self.dump_flowchart(dir_path, init_labels),
+            (Graph::Cfg, Format::Json) => return self.dump_json(dir_path, init_labels),
+            (Graph::Cfg, Format::Dot) => return self.dump_dot(dir_path, init_labels),
+            (Graph::Cfg, Format::Mermaid) => return self.dump_mermaid(dir_path, init_labels),
+            (Graph::Cfg, Format::Html) => return self.dump_html(dir_path, init_labels),
+            (Graph::Cfg, Format::Svg) => return self.dump_svg(dir_path, init_labels),
+            (Graph::Calls, format) => return self.dump_call_graph(dir_path, init_labels, format),
         }
+    }
 
-        let dir = dir_path.trim_end_matches('/').to_string();
-        let filename = dir.clone() + "/" + &self.file_name + ".json";
-        let mut file = File::create(filename).unwrap();
-        let json = serde_json::to_string(&self.asm).unwrap() + "\n"; // "\n"を足しとかないとvscodeの自動整形機能でjsonが壊れる
-        write!(file, "{}", json).unwrap();
-        file.flush().unwrap();
-    }
-
-    fn get_node<'a>(&'a self, init_file: &str, label: &str) -> Option<&'a parser::Node> {
-        let init_nodes = self.asm.get(init_file);
-        match init_nodes {
-            Some(nodes) => {
-                match parser::get_node(nodes, label) {
-                    Some(node) => {
-                        return Some(node);
-                    }
-                    None => {}
-                };
+    /// 解析結果の制御フローグラフ
+    pub fn cfg(&self) -> &cfg::Cfg {
+        return &self.cfg;
+    }
+
+    /// 解析したファイルから制御フローグラフを作り直す
+    fn build_cfg(&mut self) {
+        self.cfg = cfg::Cfg::build(&self.asm);
+    }
+
+    /// 開始ラベルのブロック(見つからないラベルは除く)
+    fn start_blocks(&self, init_labels: &[String]) -> Vec<cfg::BlockId> {
+        return init_labels
+            .iter()
+            .filter_map(|label| self.cfg.find(None, label))
+            .collect();
+    }
+
+    /// 開始ラベルから辿り着ける制御フローグラフをGraphvizのdot形式で出力する
+    pub fn dump_dot(&self, dir_path: &Path, init_labels: &[String]) -> Result<PathBuf> {
+        prepare_dir(dir_path)?;
+
+        let cfg = &self.cfg;
+        let starts = self.start_blocks(init_labels);
+        let filename = dir_path.join(self.file_name.clone() + ".dot");
+        return write_file(&filename, &cfg.to_dot(&self.asm, &self.symbols, &starts));
+    }
+
+    /// 開始ラベルから辿り着ける制御フローグラフをMermaid形式で出力する
+    pub fn dump_mermaid(&self, dir_path: &Path, init_labels: &[String]) -> Result<PathBuf> {
+        prepare_dir(dir_path)?;
+
+        let cfg = &self.cfg;
+        let starts = self.start_blocks(init_labels);
+        let filename = dir_path.join(self.file_name.clone() + ".mmd");
+        return write_file(
+            &filename,
+            &cfg.to_mermaid(&self.asm, &self.symbols, &starts),
+        );
+    }
+
+    /// 開始ラベルから辿り着ける制御フローグラフをSVGの画像として出力する
+    pub fn dump_svg(&self, dir_path: &Path, init_labels: &[String]) -> Result<PathBuf> {
+        prepare_dir(dir_path)?;
+
+        let cfg = &self.cfg;
+        let starts = self.start_blocks(init_labels);
+        let filename = dir_path.join(self.file_name.clone() + ".svg");
+        return write_file(&filename, &cfg.to_svg(&self.asm, &self.symbols, &starts));
+    }
+
+    /// 開始ラベルからの図を、callの呼び出し先に移動できるHTMLビューアとして出力する
+    pub fn dump_html(&self, dir_path: &Path, init_labels: &[String]) -> Result<PathBuf> {
+        prepare_dir(dir_path)?;
+
+        let cfg = &self.cfg;
+        let starts = self.start_blocks(init_labels);
+        let summary = match &self.header {
+            Some(header) => header.summary(),
+            None => vec![],
+        };
+        let filename = dir_path.join(self.file_name.clone() + ".html");
+        let html = html::render(
+            &self.file_name,
+            summary,
+            &self.asm,
+            &self.symbols,
+            cfg,
+            &starts,
+        )?;
+        return write_file(&filename, &html);
+    }
+
+    /// 解析結果から呼び出しグラフを作る
+    pub fn call_graph(&self, init_labels: &[String]) -> CallGraph {
+        return CallGraph::build(&self.asm, &self.cfg, init_labels);
+    }
+
+    /// 呼び出しグラフを`<name>.calls.<ext>`に出力する
+    pub fn dump_call_graph(
+        &self,
+        dir_path: &Path,
+        init_labels: &[String],
+        format: Format,
+    ) -> Result<PathBuf> {
+        prepare_dir(dir_path)?;
+
+        let graph = self.call_graph(init_labels);
+        let (ext, text) = match format {
+            Format::Flowchart => {
+                let mut text = "".to_string();
+                if let Some(header) = &self.header {
+                    text += &header.summary().join("\n");
+                    text += "\n";
+                }
+                ("flowchart", text + &graph.to_flowchart())
             }
-            None => {}
-        }
+            Format::Json => {
+                let json =
+                    serde_json::to_string(&graph).map_err(|e| Error::Serialize(e.to_string()))?;
+                ("json", json + "\n")
+            }
+            Format::Dot => ("dot", graph.to_dot()),
+            Format::Mermaid => ("mmd", graph.to_mermaid()),
+            Format::Html | Format::Svg => {
+                return Err(Error::UnsupportedFormat(
+                    format!("{:?} format is not supported for the call graph", format)
+                        .to_lowercase(),
+                ));
+            }
+        };
+        let filename = dir_path.join(format!("{}.calls.{}", self.file_name, ext));
+        return write_file(&filename, &text);
+    }
 
-        for (_, nodes) in &self.asm {
-            match parser::get_node(&nodes, label) {
-                Some(node) => return Some(node),
-                None => continue,
+    /// `::`やEXPORTで公開したコードのラベル(名前順)
+    pub fn exported_labels(&self) -> Vec<String> {
+        return self
+            .symbols
+            .exported_labels()
+            .into_iter()
+            .filter(|s| self.has_label(&s.name))
+            .map(|s| s.name.clone())
+            .collect();
+    }
+
+    /// asmファイルから開始ラベルを探す
+    ///
+    /// ROM0の$0100とrst/割り込みベクタ($0000-$0060)に置いたSECTIONのコードをアドレス順に並べ、
+    /// その後にどこからも呼ばれないルーチンを並べる
+    pub fn discover_entries(&self) -> Vec<String> {
+        let mut vectors = vec![];
+        for section in &self.sections {
+            let address = match section.address {
+                Some(address) if section.kind == "ROM0" => address,
+                _ => continue,
+            };
+            if address != 0x100 && (address > 0x60 || address % 8 != 0) {
+                continue;
+            }
+            let node = match self.asm.get(&section.file) {
+                Some(nodes) => nodes.get(&section.label),
+                None => None,
             };
+            if let Some(node) = node {
+                if let Some(label) = section_entry(&section.label, &node.next, &node.elements) {
+                    vectors.push((address, section.label.clone(), label));
+                }
+            }
         }
+        vectors.sort();
 
-        return None;
+        let mut entries: Vec<String> = vec![];
+        for (_, _, label) in &vectors {
+            if !entries.contains(label) {
+                entries.push(label.clone());
+            }
+        }
+        // callやjpで辿れないルーチン(ジャンプテーブルから呼ぶものなど)
+        let graph = CallGraph::build(&self.asm, &self.cfg, &[]);
+        for routine in graph.routines() {
+            if routine.callers.is_empty()
+                && !entries.contains(&routine.name)
+                && !vectors.iter().any(|(_, key, _)| *key == routine.name)
+            {
+                entries.push(routine.name.clone());
+            }
+        }
+        return entries;
     }
 
-    /// 解析結果をflowchart形式で出力する
-    pub fn dump_flowchart(&self, dir_path: &str, init_label: &str) {
-        let path = Path::new(dir_path);
+    /// いずれかのファイルにラベルが存在するか
+    pub fn has_label(&self, label: &str) -> bool {
+        self.asm
+            .values()
+            .any(|nodes| parser::get_node(nodes, label).is_some())
+    }
 
-        if !path.exists() {
-            println!("target dir does not exist");
-            return;
+    /// 開始ラベルが全て見つかるか調べる
+    pub fn check_entries(&self, init_labels: &[String]) -> Result<()> {
+        match init_labels.iter().find(|label| !self.has_label(label)) {
+            Some(label) => return Err(Error::UnresolvedEntry(label.clone())),
+            None => return Ok(()),
         }
+    }
 
-        if !path.is_dir() {
-            println!("target path is not a directory");
-            return;
-        }
+    /// 解析結果をflowchart形式で出力する
+    pub fn dump_flowchart(&self, dir_path: &Path, init_labels: &[String]) -> Result<PathBuf> {
+        prepare_dir(dir_path)?;
 
-        let dir = dir_path.trim_end_matches('/').to_string();
-        let filename = dir.clone() + "\\" + &self.file_name + ".flowchart";
-        let mut file = File::create(filename.clone()).unwrap();
+        let filename = dir_path.join(self.file_name.clone() + ".flowchart");
 
+        let cfg = &self.cfg;
         let mut charts = "".to_string();
-        for (filename, nodes) in &self.asm {
-            let mut done = vec![];
-            let header = format!(
-                "--------------------------------------------------------------------\n[{}]",
-                filename
-            )
-            .to_string();
-            let mut ns = vec![header, "st=>start: Start".to_string()];
-            let mut flows = vec![format!("st->{}", init_label)];
-            let mut current_label = init_label;
-            let mut current_node = match parser::get_node(&nodes, init_label) {
-                Some(node) => node,
+        if let Some(header) = &self.header {
+            charts += &header.summary().join("\n");
+            charts += "\n";
+        }
+        let mut files: Vec<_> = self.asm.iter().collect();
+        files.sort_by(|a, b| a.0.cmp(b.0));
+        for init_label in init_labels {
+            for (filename, _) in &files {
+                if let Some(chart) = flowchart(cfg, filename, init_label, false) {
+                    charts += &chart;
+                }
+            }
+        }
+        return write_file(&filename, &charts);
+    }
+
+    /// ルーチン(グローバルラベル)ごとの図を`<name>/`の下にソースと同じ構成で出力する
+    ///
+    /// 出力した図の一覧は`<name>/index.md`に書き、図と一覧の順に書き出したファイルのパスを返す
+    pub fn dump_flowchart_routines(
+        &self,
+        dir_path: &Path,
+        init_labels: &[String],
+    ) -> Result<Vec<PathBuf>> {
+        let root = dir_path.join(&self.file_name);
+        prepare_dir(&root)?;
+
+        let cfg = &self.cfg;
+        let graph = CallGraph::build(&self.asm, cfg, init_labels);
+        let mut index = format!("# {}\n", self.file_name);
+        if let Some(header) = &self.header {
+            index += "\n```\n";
+            index += &header.summary().join("\n");
+            index += "\n```\n";
+        }
+        let mut current_file = None;
+        let mut paths = vec![];
+        for routine in graph.routines() {
+            let chart = match flowchart(cfg, &routine.file, &routine.name, true) {
+                Some(chart) => chart,
                 None => continue,
             };
-            loop {
-                done.push(current_label);
+            let components = routine_chart_path(&routine.file, &routine.name);
+            let path = components.iter().fold(root.clone(), |path, c| path.join(c));
+            if let Some(parent) = path.parent() {
+                prepare_dir(parent)?;
+            }
+            fs::write(&path, chart).map_err(|e| Error::io(&path, e))?;
+            paths.push(path);
+
+            if current_file != Some(&routine.file) {
+                index += &format!("\n## {}\n\n", routine.file);
+                current_file = Some(&routine.file);
+            }
+            let note = if routine.reachable {
+                ""
+            } else {
+                " (unreachable)"
+            };
+            index += &format!("- [{}]({}){}\n", routine.name, components.join("/"), note);
+        }
+        paths.push(write_file(&root.join("index.md"), &index)?);
+        return Ok(paths);
+    }
 
-                if current_node.next_cond != "" {
-                    let next_label = &current_node.next;
+    // 最初に渡したabs_file_pathのディレクトリを起点とした相対パスを得るのに必要
+    fn to_relative(&self, abs_file_path: &Path) -> Result<String> {
+        match abs_file_path.strip_prefix(&self.base_dir) {
+            Ok(rel_file_path) => return error::path_str(rel_file_path),
+            Err(_) => {
+                return Err(Error::invalid_path(
+                    abs_file_path,
+                    "is not under the directory of the input file",
+                ))
+            }
+        }
+    }
+}
+
+/// `filename`の`init_label`から辿れる範囲をflowchart.jsの1つの図にする
+///
+/// `routine_only`ならグローバルラベルの外に出たところで止める
+fn flowchart(
+    cfg: &cfg::Cfg,
+    filename: &str,
+    init_label: &str,
+    routine_only: bool,
+) -> Option<String> {
+    let header = format!(
+        "--------------------------------------------------------------------\n[{}] {}",
+        filename, init_label
+    )
+    .to_string();
+    let mut ns = vec![header, "st=>start: Start".to_string()];
+    let mut flows = vec![format!("st->{}", init_label)];
+    let start = cfg.find_in_file(filename, init_label)?;
+    let routine = parser::remove_dash(&cfg.block(start).global);
+
+    // 条件分岐の両方の行き先を辿る
+    let mut done = vec![];
+    let mut unresolved = vec![];
+    let mut remaining = VecDeque::from(vec![start]);
+    while let Some(current) = remaining.pop_front() {
+        if done.contains(&current) {
+            continue;
+        }
+        done.push(current);
+        let block = cfg.block(current);
+        let current_label = &block.label;
+
+        let mut nexts = vec![];
+        // バンクごとに解決した飛び先など、2つ目以降のjp/jrの行き先
+        let extras: Vec<&cfg::Edge> = block
+            .flow_successors()
+            .filter(|e| e.kind == cfg::EdgeKind::Taken)
+            .skip(1)
+            .collect();
+        if block.is_conditional() {
+            let taken = block.flow_successors().find(|e| e.condition.is_some());
+            let fallthrough = block
+                .flow_successors()
+                .find(|e| e.kind == cfg::EdgeKind::Fallthrough);
+            ns.push(format!("{}=>parallel:  {}", current_label, block.text));
+            // ループで戻る辺は右に出す
+            let taken_done = matches!(taken.and_then(|e| e.to), Some(id) if done.contains(&id));
+            let mut paths = if taken_done {
+                vec![fallthrough, taken]
+            } else {
+                vec![taken, fallthrough]
+            };
+            paths.extend(extras.iter().map(|e| Some(*e)));
+            parallel_flows(cfg, current_label, &paths, &mut ns, &mut flows);
+            nexts.extend(paths.into_iter().flatten());
+        } else if !extras.is_empty() {
+            ns.push(format!("{}=>parallel:  {}", current_label, block.text));
+            let mut paths = vec![block
+                .flow_successors()
+                .find(|e| e.kind != cfg::EdgeKind::Return)];
+            paths.extend(extras.iter().map(|e| Some(*e)));
+            parallel_flows(cfg, current_label, &paths, &mut ns, &mut flows);
+            nexts.extend(paths.into_iter().flatten());
+        } else {
+            ns.push(format!("{}=>operation:  {}", current_label, block.text));
+            let next = block
+                .flow_successors()
+                .find(|e| e.kind != cfg::EdgeKind::Return);
+            flows.push(format!("{}->{}", current_label, flowchart_name(cfg, next)));
+            nexts.extend(next);
+        }
+
+        for next in nexts {
+            match (next.kind, next.to) {
+                (cfg::EdgeKind::Return, _) => {}
+                (_, Some(id))
+                    if routine_only && parser::remove_dash(&cfg.block(id).global) != routine =>
+                {
+                    // 別のルーチンはそれぞれの図で描く
+                    let label = &cfg.block(id).label;
+                    if unresolved.contains(label) {
+                        continue;
+                    }
+                    unresolved.push(label.clone());
                     ns.push(format!(
-                        "{}=>parallel:  {}",
-                        current_label, current_node.text
+                        "{}=>operation:  {}\n;This routine has its own chart.",
+                        label, label
                     ));
-                    if done.contains(&&current_node.next_cond[..]) {
-                        flows.push(format!("{}(path1, right)->{}", current_label, next_label));
-                        flows.push(format!(
-                            "{}(path2, bottom)->{}",
-                            current_label, &current_node.next_cond
-                        ));
+                    flows.push(format!("{}->e", label));
+                }
+                (_, Some(id)) => remaining.push_back(id),
+                (_, None) => {
+                    if unresolved.contains(&next.label) {
+                        continue;
+                    }
+                    unresolved.push(next.label.clone());
+                    if next.label == "hl" {
+                        ns.push(format!(
+                                "{}=>operation:  This flowchart ends here, because PC jumps to HL which dynamically changes.",
+                                next.label
+                            ));
                     } else {
-                        flows.push(format!(
-                            "{}(path1, right)->{}",
-                            current_label, &current_node.next_cond
+                        ns.push(format!(
+                            "{}=>operation:  {}\n;This label is not found in the analyzed code.",
+                            next.label, next.label
                         ));
-                        flows.push(format!("{}(path2, bottom)->{}", current_label, next_label));
-                    }
-                    current_label = next_label;
-                    // println!("{}", current_label);
-                    match self.get_node(filename, current_label) {
-                        Some(node) => {
-                            current_node = node;
-                        }
-                        None => {
-                            if current_label == "hl" {
-                                ns.push(format!(
-                                    "{}=>operation:  This flowchart ends here, because PC jumps to HL which dynamically changes.",
-                                    current_label
-                                ));
-                            } else {
-                                ns.push(format!(
-                                    "{}=>operation:  {}\n;Moved to another bank.",
-                                    current_label, current_label
-                                ));
-                            }
-                            break;
-                        }
-                    }
-                } else if current_node.next != "" {
-                    let next_label = &current_node.next;
-                    ns.push(format!(
-                        "{}=>operation:  {}",
-                        current_label, current_node.text
-                    ));
-                    flows.push(format!("{}->{}", current_label, next_label));
-                    current_label = next_label;
-                    // println!("{}", current_label);
-                    match self.get_node(filename, current_label) {
-                        Some(node) => {
-                            current_node = node;
-                        }
-                        None => {
-                            if current_label == "hl" {
-                                ns.push(format!(
-                                    "{}=>operation:  This flowchart ends here, because PC jumps to HL which dynamically changes.",
-                                    current_label
-                                ));
-                            } else {
-                                ns.push(format!(
-                                    "{}=>operation:  {}\n;Moved to another bank.",
-                                    current_label, current_label
-                                ));
-                            }
-                            break;
-                        }
                     }
-                } else {
-                    ns.push(format!(
-                        "{}=>operation:  {}",
-                        current_label, current_node.text
-                    ));
-                    break;
-                }
-
-                if done.contains(&current_label) {
-                    break;
+                    flows.push(format!("{}->e", next.label));
                 }
             }
+        }
+    }
 
-            flows.push(format!("{}->e", current_label));
-            charts += &ns.join("\n");
-            charts += "\n";
-            charts += "e=>end\n";
-            charts += "\n";
-            charts += &flows.join("\n");
-            charts += "\n";
+    let mut charts = ns.join("\n");
+    charts += "\n";
+    charts += "e=>end\n";
+    charts += "\n";
+    charts += &flows.join("\n");
+    charts += "\n";
+    return Some(charts);
+}
+
+/// SECTIONの後に続くコードの開始ラベル
+///
+/// ラベルのないコードなら最初の飛び先(`nop`の後の`jp Start`など)、コードがなければ次のラベルにする
+fn section_entry(key: &str, next: &str, rest: &[parser::Element]) -> Option<String> {
+    let mut instructions = rest.iter().filter_map(|e| e.instruction()).peekable();
+    if instructions.peek().is_none() {
+        if next.is_empty() || next.contains('/') {
+            return None;
         }
-        write!(file, "{}", charts).unwrap();
-        file.flush().unwrap();
-        println!("dump into {}...", filename);
+        return Some(next.to_string());
     }
+    match instructions.find_map(branch_target) {
+        Some(target) => return Some(target.to_string()),
+        // 条件分岐の後ろのノードはラベルとして指定できない
+        None if !key.ends_with('\'') && !key.contains('/') => return Some(key.to_string()),
+        None => return None,
+    }
+}
 
-    // 最初に渡したabs_file_pathのディレクトリを起点とした相対パスを得るのに必要
-    fn to_relative(&self, abs_file_path: &str) -> String {
-        let path = Path::new(abs_file_path);
-        let rel_file_path = path
-            .strip_prefix(&self.base_dir)
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string();
-        return rel_file_path;
+/// ルーチンの図の`<name>/`からの相対パス(`src/engine.asm`の`Foo`なら`src/engine/Foo.flowchart`)
+fn routine_chart_path(file: &str, routine: &str) -> Vec<String> {
+    let mut components: Vec<String> = Path::new(file)
+        .with_extension("")
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect();
+    components.push(routine.to_string() + ".flowchart");
+    return components;
+}
+
+/// parallelのpath1〜path3で分岐の行き先をつなぐ
+///
+/// 行き先が4つ以上あれば、path3から次のparallelに続ける。行き先のない分岐(None)は終わりにつなぐ
+fn parallel_flows(
+    cfg: &cfg::Cfg,
+    label: &str,
+    paths: &[Option<&cfg::Edge>],
+    ns: &mut Vec<String>,
+    flows: &mut Vec<String>,
+) {
+    const DIRECTIONS: [&str; 3] = ["right", "bottom", "left"];
+    let mut from = label.to_string();
+    let mut paths = paths;
+    while paths.len() > DIRECTIONS.len() {
+        let more = from.clone() + "+";
+        ns.push(format!("{}=>parallel:  {}", more, more));
+        for (i, edge) in paths[..2].iter().enumerate() {
+            flows.push(format!(
+                "{}(path{}, {})->{}",
+                from,
+                i + 1,
+                DIRECTIONS[i],
+                flowchart_name(cfg, *edge)
+            ));
+        }
+        flows.push(format!("{}(path3, {})->{}", from, DIRECTIONS[2], more));
+        from = more;
+        paths = &paths[2..];
     }
+    for (i, edge) in paths.iter().enumerate() {
+        flows.push(format!(
+            "{}(path{}, {})->{}",
+            from,
+            i + 1,
+            DIRECTIONS[i],
+            flowchart_name(cfg, *edge)
+        ));
+    }
+}
+
+/// flowchart.jsでの辺の行き先の名前
+fn flowchart_name(cfg: &cfg::Cfg, edge: Option<&cfg::Edge>) -> String {
+    let edge = match edge {
+        Some(edge) => edge,
+        None => return "e".to_string(),
+    };
+    match (edge.kind, edge.to) {
+        (cfg::EdgeKind::Return, _) => "e".to_string(),
+        (_, Some(id)) => cfg.block(id).label.clone(),
+        (_, None) => edge.label.clone(),
+    }
+}
+
+/// call/jp/jrの飛び先がラベルならその名前を返す(`jp hl`や`jr @+2`などは除く)
+fn branch_target<'a>((op, operand1, operand2): (&str, &'a str, &'a str)) -> Option<&'a str> {
+    let op = op.to_ascii_uppercase();
+    if !opcode::is_call(&op) && !opcode::is_jump(&op) {
+        return None;
+    }
+    // jp cc, labelの場合は第2オペランドが飛び先
+    let target = if !operand2.is_empty() {
+        operand2
+    } else {
+        operand1
+    };
+    match expr::parse(target) {
+        Ok(Expr::Symbol(name)) if !name.eq_ignore_ascii_case("hl") && !name.starts_with('@') => {
+            return Some(target);
+        }
+        _ => return None,
+    }
+}
+
+/// 逆アセンブルしたソースを書き出す一時ディレクトリ(プロセスIDと通し番号で実行ごとに分ける)
+fn temp_workspace() -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let n = COUNT.fetch_add(1, Ordering::Relaxed);
+    return env::temp_dir().join(format!("flowchartgb-{}-{}", process::id(), n));
 }
 
-fn is_asm(file_path: &str) -> bool {
-    let path = Path::new(file_path);
+/// 出力先のディレクトリがなければ作る
+fn prepare_dir(dir_path: &Path) -> Result<()> {
+    if !dir_path.exists() {
+        fs::create_dir_all(dir_path).map_err(|e| Error::io(dir_path, e))?;
+    }
+    if !dir_path.is_dir() {
+        return Err(Error::invalid_path(dir_path, "is not a directory"));
+    }
+    Ok(())
+}
+
+fn write_file(path: &Path, text: &str) -> Result<PathBuf> {
+    let mut file = File::create(path).map_err(|e| Error::io(path, e))?;
+    write!(file, "{}", text).map_err(|e| Error::io(path, e))?;
+    file.flush().map_err(|e| Error::io(path, e))?;
+    Ok(path.to_path_buf())
+}
+
+/// ファイルのディレクトリと、拡張子を除いたファイル名
+fn split_path(path: &Path) -> Result<(PathBuf, String)> {
+    let dir = match path.parent() {
+        Some(dir) => dir.to_path_buf(),
+        None => return Err(Error::invalid_path(path, "has no parent directory")),
+    };
+    let stem = match path.file_stem() {
+        Some(stem) => error::path_str(Path::new(stem))?,
+        None => return Err(Error::invalid_path(path, "has no file name")),
+    };
+    return Ok((dir, stem));
+}
+
+fn is_asm(path: &Path) -> bool {
     match path.extension() {
         Some(ext) if ext == "asm" => {
             return true;
@@ -309,3 +934,150 @@ fn is_asm(file_path: &str) -> bool {
         }
     }
 }
+
+#[test]
+fn test_paths() {
+    let (dir, name) = split_path(Path::new("/home/user/game/main.asm")).unwrap();
+    assert_eq!(dir, Path::new("/home/user/game"));
+    assert_eq!(name, "main");
+    assert!(is_asm(Path::new("/home/user/game/main.asm")));
+    assert!(!is_asm(Path::new("/home/user/game/data.inc")));
+
+    let mut analyzer = Analyzer::new();
+    analyzer.base_dir = dir;
+    assert_eq!(
+        analyzer
+            .to_relative(Path::new("/home/user/game/src/engine.asm"))
+            .unwrap(),
+        Path::new("src").join("engine.asm").to_string_lossy()
+    );
+    assert!(analyzer.to_relative(Path::new("/tmp/main.asm")).is_err());
+
+    let engine = Path::new("src").join("engine.asm");
+    assert_eq!(
+        routine_chart_path(&engine.to_string_lossy(), "Foo"),
+        vec!["src", "engine", "Foo.flowchart"]
+    );
+}
+
+#[test]
+fn test_discover_entries() {
+    let text = "SECTION \"rst38\", ROM0[$38]
+\trst $38
+SECTION \"vblank\", ROM0[$40]
+\tjp VBlank
+SECTION \"entry\", ROM0[$100]
+\tnop
+\tjp Init
+SECTION \"code\", ROM0[$150]
+Init::
+\tcall Sub
+\tret
+Sub:
+\tret
+VBlank:
+\treti
+Unused:
+\tret
+Exported::
+\tret
+main:
+\tret
+";
+    let (result, _, mut p) = parser::parse_snippet(text);
+    let mut analyzer = Analyzer::new();
+    analyzer.asm = result;
+    analyzer.symbols = p.take_symbols();
+    analyzer.sections = p.take_sections();
+    analyzer.build_cfg();
+    assert_eq!(
+        analyzer.discover_entries(),
+        ["ROM0_0038", "VBlank", "Init", "Exported", "Unused", "main"]
+    );
+    assert_eq!(analyzer.exported_labels(), ["Exported", "Init"]);
+}
+
+#[test]
+fn test_flowchart_alternatives() {
+    // 逆アセンブルでバンクごとに解決したjpの飛び先
+    let mut p = parser::Parser::with_text(
+        vec![],
+        Default::default(),
+        Default::default(),
+        HashMap::new(),
+    );
+    let fars: Vec<String> = (1..=4).map(|i| format!("Far_{}", i)).collect();
+    p.push_label("start:");
+    p.push_instruction(
+        "    jp Far_1".to_string(),
+        "jp",
+        "Far_1",
+        "",
+        fars[1..].to_vec(),
+    );
+    for far in &fars {
+        p.push_label(&(far.clone() + ":"));
+        p.push_instruction("    ret".to_string(), "ret", "", "", vec![]);
+    }
+    let mut asm = HashMap::new();
+    asm.insert("bank.asm".to_string(), p.into_nodes());
+    let cfg = cfg::Cfg::build(&asm);
+
+    let chart = flowchart(&cfg, "bank.asm", "start", false).unwrap();
+    let flows = [
+        "start(path1, right)->Far_1",
+        "start(path2, bottom)->Far_2",
+        "start(path3, left)->start+",
+        "start+(path1, right)->Far_3",
+        "start+(path2, bottom)->Far_4",
+    ];
+    for flow in &flows {
+        assert!(chart.lines().any(|l| l == *flow), "{}", flow);
+    }
+    for far in &fars {
+        assert!(chart.contains(&format!("{}=>operation:", far)));
+    }
+}
+
+#[test]
+fn test_flowchart_branches() {
+    let text = "start:
+\tcp 1
+\tjr nz, .other
+\tld a, 2
+\tjp Done
+.other
+\tld a, 3
+\tjp Other
+Done:
+\tret
+Other:
+\tret
+";
+    let (mut result, _, _) = parser::parse_snippet(text);
+    let cfg = cfg::Cfg::build(&result);
+    let chart = flowchart(&cfg, "main.asm", "start", false).unwrap();
+    // 条件分岐の成立と不成立の両方から別々のラベルまで辿る
+    for flow in &[
+        "start(path1, right)->start/.other",
+        "start(path2, bottom)->start'",
+        "start'->Done",
+        "start/.other->Other",
+        "Done->e",
+        "Other->e",
+    ] {
+        assert!(chart.lines().any(|l| l == *flow), "{}", flow);
+    }
+
+    // 不成立の行き先がなければ終わりにつなぐ
+    let start = result
+        .get_mut("main.asm")
+        .unwrap()
+        .get_mut("start")
+        .unwrap();
+    start.next = "".to_string();
+    let cfg = cfg::Cfg::build(&result);
+    let chart = flowchart(&cfg, "main.asm", "start", false).unwrap();
+    assert!(chart.lines().any(|l| l == "start(path2, bottom)->e"));
+    assert!(chart.contains("Other=>operation:"));
+}
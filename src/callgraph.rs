@@ -0,0 +1,582 @@
+use std::collections::{HashMap, VecDeque};
+
+extern crate serde;
+use serde::{Deserialize, Serialize};
+
+use super::cfg::{Cfg, EdgeKind};
+use super::dot::quote;
+use super::mermaid;
+use super::parser::{self, Nodes};
+
+pub type RoutineId = usize;
+
+/// 呼び出しの種類
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    /// call/callba/farcallで呼び出す
+    Call,
+    /// jp/jrや次のラベルへの流れ込みで別のルーチンに移る(末尾呼び出し)
+    Jump,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Call {
+    pub kind: CallKind,
+    /// 呼び出し先のラベル
+    pub label: String,
+    /// 呼び出し先のルーチン(解析したコード内に見つからない場合はNone)
+    pub to: Option<RoutineId>,
+}
+
+/// グローバルラベルを単位にしたルーチン
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Routine {
+    pub id: RoutineId,
+    pub name: String,
+    pub file: String,
+    pub calls: Vec<Call>,
+    pub callers: Vec<RoutineId>,
+    /// 自分自身を(間接的に)呼び出すか
+    pub recursive: bool,
+    /// 開始ラベルから辿り着けるか
+    pub reachable: bool,
+}
+
+/// ルーチン間の呼び出しグラフ
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(from = "SerializedCallGraph")]
+pub struct CallGraph {
+    routines: Vec<Routine>,
+    /// (ファイル名, ルーチン名)からルーチンへの対応表
+    #[serde(skip)]
+    index: HashMap<String, HashMap<String, RoutineId>>,
+}
+
+/// 読み込んだ呼び出しグラフ(対応表はルーチンから作り直す)
+#[derive(Deserialize)]
+struct SerializedCallGraph {
+    routines: Vec<Routine>,
+}
+
+impl From<SerializedCallGraph> for CallGraph {
+    fn from(graph: SerializedCallGraph) -> Self {
+        return CallGraph::from_routines(graph.routines);
+    }
+}
+
+impl CallGraph {
+    /// `Node::calls`と制御フローグラフから呼び出しグラフを作る
+    ///
+    /// `roots`(開始ラベル)から辿り着けないルーチンは`reachable`がfalseになる
+    pub fn build(asm: &HashMap<String, Nodes>, cfg: &Cfg, roots: &[String]) -> Self {
+        let mut graph = CallGraph::default();
+        let mut files: Vec<_> = asm.iter().collect();
+        files.sort_by(|a, b| a.0.cmp(b.0));
+
+        // ルーチンを集める(命令を含まない定数やデータのラベルは除く)
+        for (file, nodes) in &files {
+            let mut labels: Vec<_> = nodes.iter().collect();
+            labels.sort_by(|a, b| a.0.cmp(b.0));
+            for (_, node) in labels {
+                if node.elements.iter().all(|e| e.instruction().is_none()) {
+                    continue;
+                }
+                graph.push_routine(file, &parser::remove_dash(&node.global));
+            }
+        }
+
+        // callで呼び出すルーチン
+        for (file, nodes) in &files {
+            let mut labels: Vec<_> = nodes.iter().collect();
+            labels.sort_by(|a, b| a.0.cmp(b.0));
+            for (_, node) in labels {
+                let from = match graph.find_in_file(file, &node.global) {
+                    Some(id) => id,
+                    None => continue,
+                };
+                for callee in &node.calls {
+                    // ローカルラベルの呼び出しはルーチン内で完結している
+                    if callee.starts_with('.') {
+                        continue;
+                    }
+                    let to = graph.find(Some(file), callee);
+                    graph.add_call(from, CallKind::Call, callee, to);
+                }
+            }
+        }
+
+        // ルーチンの外へのジャンプ
+        for block in cfg.blocks() {
+            // 無条件ジャンプの後ろのノードはどこからも来ないので、次のラベルへの流れ込みを数えない
+            if block.node.ends_with('\'') && block.predecessors.is_empty() {
+                continue;
+            }
+            let from = match graph.find_in_file(&block.file, &block.global) {
+                Some(id) => id,
+                None => continue,
+            };
+            for edge in block.flow_successors() {
+                if edge.kind == EdgeKind::Return {
+                    continue;
+                }
+                let to = match edge.to {
+                    Some(to) => cfg.block(to),
+                    None => continue,
+                };
+                if let Some(to) = graph.find_in_file(&to.file, &to.global) {
+                    if to != from {
+                        let label = graph.routines[to].name.clone();
+                        graph.add_call(from, CallKind::Jump, &label, Some(to));
+                    }
+                }
+            }
+        }
+
+        // 呼び出し元を埋める
+        for id in 0..graph.routines.len() {
+            let callees: Vec<RoutineId> = graph.routines[id]
+                .calls
+                .iter()
+                .filter_map(|c| c.to)
+                .collect();
+            for to in callees {
+                if !graph.routines[to].callers.contains(&id) {
+                    graph.routines[to].callers.push(id);
+                }
+            }
+        }
+
+        graph.mark_recursive();
+        graph.mark_reachable(roots);
+        return graph;
+    }
+
+    /// 作ったルーチンから呼び出しグラフを組み立て直す
+    pub fn from_routines(routines: Vec<Routine>) -> Self {
+        let mut index: HashMap<String, HashMap<String, RoutineId>> = HashMap::new();
+        for routine in &routines {
+            index
+                .entry(routine.file.clone())
+                .or_default()
+                .insert(routine.name.clone(), routine.id);
+        }
+        return CallGraph { routines, index };
+    }
+
+    fn push_routine(&mut self, file: &str, name: &str) {
+        let labels = self.index.entry(file.to_string()).or_default();
+        if labels.contains_key(name) {
+            return;
+        }
+        let id = self.routines.len();
+        labels.insert(name.to_string(), id);
+        self.routines.push(Routine {
+            id,
+            name: name.to_string(),
+            file: file.to_string(),
+            calls: vec![],
+            callers: vec![],
+            recursive: false,
+            reachable: true,
+        });
+    }
+
+    fn add_call(&mut self, from: RoutineId, kind: CallKind, label: &str, to: Option<RoutineId>) {
+        let calls = &mut self.routines[from].calls;
+        if calls.iter().any(|c| c.kind == kind && c.label == label) {
+            return;
+        }
+        calls.push(Call {
+            kind,
+            label: label.to_string(),
+            to,
+        });
+    }
+
+    /// 強連結成分を求めて、成分内のcallで自分に戻ってくるルーチンを再帰とする
+    fn mark_recursive(&mut self) {
+        let components = self.components();
+        let mut component_of = vec![0; self.routines.len()];
+        for (i, component) in components.iter().enumerate() {
+            for &id in component {
+                component_of[id] = i;
+            }
+        }
+        for component in &components {
+            let recursive = component.iter().any(|&id| {
+                self.routines[id]
+                    .calls
+                    .iter()
+                    .filter(|c| c.kind == CallKind::Call)
+                    .filter_map(|c| c.to)
+                    .any(|to| component_of[to] == component_of[id])
+            });
+            for &id in component {
+                self.routines[id].recursive = recursive;
+            }
+        }
+    }
+
+    /// Tarjanのアルゴリズムで強連結成分に分ける
+    ///
+    /// 呼び出しの連鎖が深くてもスタックがあふれないように、再帰せずに辿る
+    fn components(&self) -> Vec<Vec<RoutineId>> {
+        let n = self.routines.len();
+        let mut index: Vec<Option<usize>> = vec![None; n];
+        let mut lowlink = vec![0; n];
+        let mut on_stack = vec![false; n];
+        let mut stack: Vec<RoutineId> = vec![];
+        let mut count = 0;
+        let mut components = vec![];
+
+        for root in 0..n {
+            if index[root].is_some() {
+                continue;
+            }
+            // (辿っているルーチン, 次に調べる呼び出しの位置)
+            let mut visiting = vec![(root, 0)];
+            index[root] = Some(count);
+            lowlink[root] = count;
+            count += 1;
+            stack.push(root);
+            on_stack[root] = true;
+
+            while let Some((id, next)) = visiting.pop() {
+                if let Some(call) = self.routines[id].calls.get(next) {
+                    visiting.push((id, next + 1));
+                    let to = match call.to {
+                        Some(to) => to,
+                        None => continue,
+                    };
+                    match index[to] {
+                        None => {
+                            index[to] = Some(count);
+                            lowlink[to] = count;
+                            count += 1;
+                            stack.push(to);
+                            on_stack[to] = true;
+                            visiting.push((to, 0));
+                        }
+                        Some(i) if on_stack[to] => lowlink[id] = lowlink[id].min(i),
+                        Some(_) => {}
+                    }
+                    continue;
+                }
+
+                // 呼び出し先を全て辿り終えたら、呼び出し元に戻る
+                if let Some(&(parent, _)) = visiting.last() {
+                    lowlink[parent] = lowlink[parent].min(lowlink[id]);
+                }
+                if Some(lowlink[id]) == index[id] {
+                    let mut component = vec![];
+                    while let Some(member) = stack.pop() {
+                        on_stack[member] = false;
+                        component.push(member);
+                        if member == id {
+                            break;
+                        }
+                    }
+                    components.push(component);
+                }
+            }
+        }
+        return components;
+    }
+
+    fn mark_reachable(&mut self, roots: &[String]) {
+        let roots: Vec<RoutineId> = roots.iter().filter_map(|r| self.find(None, r)).collect();
+        if roots.is_empty() {
+            return;
+        }
+        let mut reachable = vec![false; self.routines.len()];
+        let mut remaining: VecDeque<RoutineId> = roots.into_iter().collect();
+        while let Some(id) = remaining.pop_front() {
+            if reachable[id] {
+                continue;
+            }
+            reachable[id] = true;
+            remaining.extend(self.routines[id].calls.iter().filter_map(|c| c.to));
+        }
+        for (routine, reachable) in self.routines.iter_mut().zip(reachable) {
+            routine.reachable = reachable;
+        }
+    }
+
+    /// ラベルを含むルーチンを探す(`file`が与えられればそのファイルを優先する)
+    pub fn find(&self, file: Option<&str>, label: &str) -> Option<RoutineId> {
+        if let Some(id) = file.and_then(|f| self.find_in_file(f, label)) {
+            return Some(id);
+        }
+        let mut files: Vec<_> = self.index.keys().collect();
+        files.sort();
+        return files
+            .into_iter()
+            .find_map(|file| self.find_in_file(file, label));
+    }
+
+    /// `file`の中だけでラベルを含むルーチンを探す
+    ///
+    /// ローカルラベル(`global/.local`, `global.local`)はそのグローバルラベルのルーチンになる
+    pub fn find_in_file(&self, file: &str, label: &str) -> Option<RoutineId> {
+        let global = label.split('/').next().unwrap_or(label);
+        let global = global.split('.').next().unwrap_or(global);
+        let global = parser::remove_dash(global.trim_end_matches(':'));
+        return self.index.get(file)?.get(&global).cloned();
+    }
+
+    pub fn routines(&self) -> &[Routine] {
+        return &self.routines;
+    }
+
+    pub fn into_routines(self) -> Vec<Routine> {
+        return self.routines;
+    }
+
+    /// Graphvizのdot形式にする
+    ///
+    /// ファイルごとにclusterにまとめ、jpでの移動は破線、再帰は二重枠、到達できないルーチンは灰色で描く
+    pub fn to_dot(&self) -> String {
+        let mut lines = vec![
+            "digraph calls {".to_string(),
+            "    node [shape=box, fontname=\"monospace\"];".to_string(),
+        ];
+        let mut files: Vec<_> = self.index.keys().collect();
+        files.sort();
+        for (i, file) in files.into_iter().enumerate() {
+            lines.push(format!("    subgraph cluster_{} {{", i));
+            lines.push(format!("        label={};", quote(file)));
+            for routine in self.routines.iter().filter(|r| &r.file == file) {
+                let mut attributes = vec![format!("label={}", quote(&routine.name))];
+                if routine.recursive {
+                    attributes.push("peripheries=2".to_string());
+                }
+                if !routine.reachable {
+                    attributes.push("color=gray, fontcolor=gray".to_string());
+                }
+                lines.push(format!(
+                    "        r{} [{}];",
+                    routine.id,
+                    attributes.join(", ")
+                ));
+            }
+            lines.push("    }".to_string());
+        }
+
+        let mut missing = vec![];
+        for routine in &self.routines {
+            for call in &routine.calls {
+                let to = match call.to {
+                    Some(to) => format!("r{}", to),
+                    None => {
+                        if !missing.contains(&call.label) {
+                            missing.push(call.label.clone());
+                            lines.push(format!("    {} [shape=plaintext];", quote(&call.label)));
+                        }
+                        quote(&call.label)
+                    }
+                };
+                let style = match call.kind {
+                    CallKind::Call => "",
+                    CallKind::Jump => " [style=dashed]",
+                };
+                lines.push(format!("    r{} -> {}{};", routine.id, to, style));
+            }
+        }
+        lines.push("}".to_string());
+        return lines.join("\n") + "\n";
+    }
+
+    /// Mermaidの`flowchart LR`にする
+    ///
+    /// to_dotと同様にファイルごとにsubgraphにまとめ、jpでの移動は点線にする
+    pub fn to_mermaid(&self) -> String {
+        let mut lines = vec![
+            "flowchart LR".to_string(),
+            "    classDef unreachable color:#999,stroke:#999".to_string(),
+        ];
+        let mut files: Vec<_> = self.index.keys().collect();
+        files.sort();
+        for (i, file) in files.into_iter().enumerate() {
+            lines.push(format!(
+                "    subgraph f{} [\"{}\"]",
+                i,
+                mermaid::escape(file)
+            ));
+            for routine in self.routines.iter().filter(|r| &r.file == file) {
+                let node = if routine.recursive {
+                    format!("r{}[[\"{}\"]]", routine.id, mermaid::escape(&routine.name))
+                } else {
+                    format!("r{}[\"{}\"]", routine.id, mermaid::escape(&routine.name))
+                };
+                let class = if routine.reachable {
+                    ""
+                } else {
+                    ":::unreachable"
+                };
+                lines.push(format!("        {}{}", node, class));
+            }
+            lines.push("    end".to_string());
+        }
+
+        let mut missing: Vec<&str> = vec![];
+        for routine in &self.routines {
+            for call in &routine.calls {
+                let to = match call.to {
+                    Some(to) => format!("r{}", to),
+                    None => {
+                        let i = match missing.iter().position(|l| *l == call.label) {
+                            Some(i) => i,
+                            None => {
+                                missing.push(&call.label);
+                                lines.push(format!(
+                                    "    u{}[\"{}\"]",
+                                    missing.len() - 1,
+                                    mermaid::escape(&call.label)
+                                ));
+                                missing.len() - 1
+                            }
+                        };
+                        format!("u{}", i)
+                    }
+                };
+                let arrow = match call.kind {
+                    CallKind::Call => "-->",
+                    CallKind::Jump => "-.->",
+                };
+                lines.push(format!("    r{} {} {}", routine.id, arrow, to));
+            }
+        }
+        return lines.join("\n") + "\n";
+    }
+
+    /// flowchart.jsのDSLにする
+    ///
+    /// ルーチンごとに、呼び出すルーチンを呼び出す順にsubroutineとして並べる
+    pub fn to_flowchart(&self) -> String {
+        let mut charts = vec![];
+        for routine in &self.routines {
+            let mut name = routine.name.clone();
+            if routine.recursive {
+                name += " (recursive)";
+            }
+            if !routine.reachable {
+                name += " (unreachable)";
+            }
+            let mut ns = vec![
+                "--------------------------------------------------------------------".to_string(),
+                format!("[{}] {}", routine.file, routine.name),
+                format!("st=>start: {}", name),
+            ];
+            let mut flows = vec![];
+            let mut prev = "st".to_string();
+            for (i, call) in routine.calls.iter().enumerate() {
+                let current = format!("c{}", i);
+                let op = match call.kind {
+                    CallKind::Call => "call",
+                    CallKind::Jump => "jp",
+                };
+                match call.to {
+                    Some(_) => ns.push(format!("{}=>subroutine: {} {}", current, op, call.label)),
+                    None => ns.push(format!(
+                        "{}=>subroutine: {} {}\n;This label is not found in the analyzed code.",
+                        current, op, call.label
+                    )),
+                }
+                flows.push(format!("{}->{}", prev, current));
+                prev = current;
+            }
+            ns.push("e=>end".to_string());
+            flows.push(format!("{}->e", prev));
+            charts.push(ns.join("\n") + "\n\n" + &flows.join("\n") + "\n");
+        }
+        return charts.join("");
+    }
+}
+
+#[test]
+fn test_build_call_graph() {
+    let text = "start:
+\tcall sub
+\tcall missing
+\tjp tail
+sub:
+\tcall .inner
+\tcall sub
+.inner
+\tret
+tail:
+\tcall loop_a
+\tret
+loop_a:
+\tcall loop_b
+\tret
+loop_b:
+\tcall loop_a
+\tret
+dead:
+\tcall sub
+\tret
+";
+    let (asm, _, _) = parser::parse_snippet(text);
+    let cfg = Cfg::build(&asm);
+    let graph = CallGraph::build(&asm, &cfg, &["start".to_string()]);
+
+    let routine = |name: &str| &graph.routines[graph.find(None, name).unwrap()];
+    assert_eq!(graph.routines.len(), 6);
+    let start = routine("start");
+    let labels: Vec<(CallKind, &str)> =
+        start.calls.iter().map(|c| (c.kind, &c.label[..])).collect();
+    assert_eq!(
+        labels,
+        vec![
+            (CallKind::Call, "sub"),
+            (CallKind::Call, "missing"),
+            (CallKind::Jump, "tail")
+        ]
+    );
+    assert_eq!(start.calls[1].to, None);
+    assert_eq!(graph.find(None, "sub/.inner"), graph.find(None, "sub"));
+
+    assert!(routine("sub").recursive);
+    assert!(routine("loop_a").recursive && routine("loop_b").recursive);
+    assert!(!start.recursive && !routine("tail").recursive);
+
+    assert!(routine("loop_b").reachable);
+    assert!(!routine("dead").reachable);
+    assert!(routine("sub").callers.contains(&routine("dead").id));
+
+    let dot = graph.to_dot();
+    assert!(dot.contains("\"missing\" [shape=plaintext];"));
+    assert!(dot.contains("[style=dashed]"));
+    assert!(graph
+        .to_flowchart()
+        .contains("st=>start: dead (unreachable)"));
+
+    // 読み込んだグラフでもルーチンを探せる
+    let json = serde_json::to_string(&graph).unwrap();
+    let loaded: CallGraph = serde_json::from_str(&json).unwrap();
+    assert_eq!(loaded.routines(), graph.routines());
+    assert_eq!(loaded.find(None, "sub/.inner"), graph.find(None, "sub"));
+    assert_eq!(
+        loaded.find_in_file("main.asm", "loop_b"),
+        graph.find_in_file("main.asm", "loop_b")
+    );
+    assert_eq!(loaded.to_dot(), graph.to_dot());
+}
+
+#[test]
+fn test_deep_call_chain() {
+    // 深い呼び出しの連鎖でもスタックがあふれない
+    let n = 100000;
+    let mut graph = CallGraph::default();
+    for i in 0..n {
+        graph.push_routine("main.asm", &format!("r{}", i));
+    }
+    for i in 0..n {
+        let to = (i + 1) % n;
+        graph.add_call(i, CallKind::Call, &format!("r{}", to), Some(to));
+    }
+    let components = graph.components();
+    assert_eq!(components.len(), 1);
+    assert_eq!(components[0].len(), n);
+}
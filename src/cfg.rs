@@ -0,0 +1,935 @@
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+
+extern crate serde;
+use serde::{Deserialize, Serialize};
+
+use super::dot;
+use super::mermaid;
+use super::parser::{self, Node, Nodes};
+use super::svg;
+use super::symbol::SymbolTable;
+
+pub type BlockId = usize;
+
+/// 辺の種類
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    /// 次の命令へそのまま進む(条件分岐で不成立の場合も含む)
+    Fallthrough,
+    /// jp/jrで飛ぶ(条件分岐で成立した場合も含む)
+    Taken,
+    /// callで呼び出す(呼び出し後はFallthroughかTakenの辺で続く)
+    Call,
+    /// ret/retiで呼び出し元に戻る
+    Return,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub kind: EdgeKind,
+    /// 飛び先のラベル(Returnなら空)
+    pub label: String,
+    /// 飛び先のブロック(Returnや、解析したコード内に見つからない場合はNone)
+    pub to: Option<BlockId>,
+    /// 条件分岐の条件(nz, z, nc, c)
+    pub condition: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BasicBlock {
+    pub id: BlockId,
+    /// ブロックの名前(ノードの先頭のブロックはノードのラベル、以降は`label#1`, `label#2`, ...)
+    pub label: String,
+    /// ブロックが含まれるノードのラベル
+    pub node: String,
+    pub file: String,
+    pub global: String,
+    /// ノードを置いたSECTIONの名前
+    #[serde(default)]
+    pub section: Option<String>,
+    /// ノードの`elements`のうちこのブロックに含まれる範囲
+    pub instructions: Range<usize>,
+    pub text: String,
+    pub successors: Vec<Edge>,
+    pub predecessors: Vec<BlockId>,
+}
+
+impl BasicBlock {
+    /// 条件分岐で終わるブロックか
+    pub fn is_conditional(&self) -> bool {
+        return self
+            .successors
+            .iter()
+            .any(|e| e.kind != EdgeKind::Call && e.condition.is_some());
+    }
+
+    /// call以外の後続
+    pub fn flow_successors(&self) -> impl Iterator<Item = &Edge> {
+        return self.successors.iter().filter(|e| e.kind != EdgeKind::Call);
+    }
+}
+
+/// 図に描く辺の行き先
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Target {
+    Block(BlockId),
+    /// 解析したコード内に見つからないラベル(`flow_edges`が返すラベルの添字)
+    Missing(usize),
+    /// ret/retiや後続のないブロックから進む終端
+    End,
+}
+
+/// 図に描くブロックからの辺
+struct FlowEdge {
+    from: BlockId,
+    to: Target,
+    /// 条件分岐の成立/不成立の辺に付けるラベル(`true (nz)`, `false`)
+    label: Option<String>,
+}
+
+/// 基本ブロックを単位にした制御フローグラフ
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Cfg {
+    blocks: Vec<BasicBlock>,
+    /// (ファイル名, ラベル)からブロックへの対応表
+    index: HashMap<String, HashMap<String, BlockId>>,
+}
+
+const CONDITIONS: [&str; 4] = ["nz", "z", "nc", "c"];
+
+/// 分岐命令の種類
+enum Terminator {
+    Return(Option<String>),
+    JumpHl,
+}
+
+/// オペランドが条件分岐の条件なら小文字にして返す(`NZ`も`nz`として扱う)
+fn condition(operand: &str) -> Option<String> {
+    let operand = operand.to_ascii_lowercase();
+    if CONDITIONS.contains(&&operand[..]) {
+        return Some(operand);
+    }
+    return None;
+}
+
+fn terminator(op: &str, operand1: &str) -> Option<Terminator> {
+    match &op.to_ascii_lowercase()[..] {
+        "ret" if condition(operand1).is_some() => Some(Terminator::Return(condition(operand1))),
+        "ret" | "reti" => Some(Terminator::Return(None)),
+        "jp" if operand1.eq_ignore_ascii_case("hl") => Some(Terminator::JumpHl),
+        _ => None,
+    }
+}
+
+impl Cfg {
+    /// 解析結果のノードから制御フローグラフを作る
+    ///
+    /// ノードはret/reti/jp hlの直後でさらに分割する
+    pub fn build(asm: &HashMap<String, Nodes>) -> Self {
+        let mut cfg = Cfg::default();
+        // ブロックに分割した後に辺を張るので、(ブロック, ノード)を記録しておく
+        let mut pending: Vec<(BlockId, &Node, bool)> = vec![];
+
+        let mut files: Vec<_> = asm.iter().collect();
+        files.sort_by(|a, b| a.0.cmp(b.0));
+        for (file, nodes) in files {
+            let mut labels: Vec<_> = nodes.iter().collect();
+            labels.sort_by(|a, b| a.0.cmp(b.0));
+            for (label, node) in labels {
+                cfg.split_node(file, label, node, &mut pending);
+            }
+        }
+
+        // ノードの終わりの辺を張る
+        for (id, node, terminated) in pending {
+            let file = cfg.blocks[id].file.clone();
+            if terminated {
+                continue;
+            }
+            if !node.next_cond.is_empty() {
+                let condition = last_condition(node);
+                cfg.add_edge(
+                    id,
+                    &file,
+                    EdgeKind::Taken,
+                    &node.next_cond,
+                    condition.clone(),
+                );
+                for label in last_alternatives(node) {
+                    cfg.add_edge(id, &file, EdgeKind::Taken, label, condition.clone());
+                }
+                if !node.next.is_empty() {
+                    cfg.add_edge(id, &file, EdgeKind::Fallthrough, &node.next, None);
+                }
+            } else if !node.next.is_empty() {
+                let kind = if ends_with_jump(node) {
+                    EdgeKind::Taken
+                } else {
+                    EdgeKind::Fallthrough
+                };
+                cfg.add_edge(id, &file, kind, &node.next, None);
+                if kind == EdgeKind::Taken {
+                    for label in last_alternatives(node) {
+                        cfg.add_edge(id, &file, EdgeKind::Taken, label, None);
+                    }
+                }
+            }
+        }
+
+        // callの辺は呼び出し先のブロックができる前に張っているので解決し直す
+        for id in 0..cfg.blocks.len() {
+            let file = cfg.blocks[id].file.clone();
+            for i in 0..cfg.blocks[id].successors.len() {
+                let edge = &cfg.blocks[id].successors[i];
+                if edge.to.is_none() && edge.kind == EdgeKind::Call {
+                    let to = cfg.find(Some(&file), &edge.label);
+                    cfg.blocks[id].successors[i].to = to;
+                }
+            }
+        }
+
+        // 先行ブロックを埋める
+        for id in 0..cfg.blocks.len() {
+            let targets: Vec<BlockId> = cfg.blocks[id]
+                .successors
+                .iter()
+                .filter_map(|e| e.to)
+                .collect();
+            for to in targets {
+                if !cfg.blocks[to].predecessors.contains(&id) {
+                    cfg.blocks[to].predecessors.push(id);
+                }
+            }
+        }
+        return cfg;
+    }
+
+    fn split_node<'a>(
+        &mut self,
+        file: &str,
+        label: &str,
+        node: &'a Node,
+        pending: &mut Vec<(BlockId, &'a Node, bool)>,
+    ) {
+        let mut start = 0;
+        let mut count = 0;
+        // 直前のブロックの終わり(ret cc以外)
+        let mut ended: Option<Vec<Edge>> = None;
+        let mut edges: Vec<Edge> = vec![];
+        let mut calls: Vec<String> = vec![];
+
+        for (i, element) in node.elements.iter().enumerate() {
+            let (op, operand1, operand2) = match element.instruction() {
+                Some(inst) => inst,
+                None => continue,
+            };
+            if let Some(prev) = ended.take() {
+                // ret/jp hlの後に命令が続くなら新しいブロックにする
+                self.push_block(file, label, node, count, start..i, prev, &calls);
+                calls.clear();
+                count += 1;
+                start = i;
+            }
+            if parser_is_call(op) {
+                let callee = if !operand2.is_empty() {
+                    operand2
+                } else {
+                    operand1
+                };
+                calls.push(callee.to_string());
+                calls.extend(element.alternatives().iter().cloned());
+            }
+            match terminator(op, operand1) {
+                Some(Terminator::Return(Some(condition))) => {
+                    // ret ccは次の命令へのFallthroughも持つので、その場でブロックを区切る
+                    edges.push(Edge {
+                        kind: EdgeKind::Return,
+                        label: "".to_string(),
+                        to: None,
+                        condition: Some(condition),
+                    });
+                    let id = self.push_block(file, label, node, count, start..i + 1, edges, &calls);
+                    calls.clear();
+                    edges = vec![];
+                    count += 1;
+                    start = i + 1;
+                    let next = format!("{}#{}", label, count);
+                    self.blocks[id].successors.push(Edge {
+                        kind: EdgeKind::Fallthrough,
+                        label: next,
+                        to: Some(id + 1),
+                        condition: None,
+                    });
+                }
+                Some(Terminator::Return(None)) => {
+                    edges.push(Edge {
+                        kind: EdgeKind::Return,
+                        label: "".to_string(),
+                        to: None,
+                        condition: None,
+                    });
+                    ended = Some(std::mem::take(&mut edges));
+                }
+                Some(Terminator::JumpHl) => {
+                    edges.push(Edge {
+                        kind: EdgeKind::Taken,
+                        label: "hl".to_string(),
+                        to: None,
+                        condition: None,
+                    });
+                    ended = Some(std::mem::take(&mut edges));
+                }
+                None => {}
+            }
+        }
+
+        let terminated = ended.is_some();
+        let edges = ended.unwrap_or(edges);
+        let id = self.push_block(
+            file,
+            label,
+            node,
+            count,
+            start..node.elements.len(),
+            edges,
+            &calls,
+        );
+        pending.push((id, node, terminated));
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn push_block(
+        &mut self,
+        file: &str,
+        label: &str,
+        node: &Node,
+        count: usize,
+        instructions: Range<usize>,
+        successors: Vec<Edge>,
+        calls: &[String],
+    ) -> BlockId {
+        let id = self.blocks.len();
+        let name = if count == 0 {
+            label.to_string()
+        } else {
+            format!("{}#{}", label, count)
+        };
+        let mut text = if count == 0 {
+            node.header().to_string()
+        } else {
+            "".to_string()
+        };
+        for element in &node.elements[instructions.clone()] {
+            text += element.text();
+            text += "\n";
+        }
+        self.index
+            .entry(file.to_string())
+            .or_default()
+            .insert(name.clone(), id);
+        self.blocks.push(BasicBlock {
+            id,
+            label: name,
+            node: label.to_string(),
+            file: file.to_string(),
+            global: node.global.clone(),
+            section: node.section.clone(),
+            instructions,
+            text,
+            successors,
+            predecessors: vec![],
+        });
+        for callee in calls {
+            let callee = if callee.starts_with('.') {
+                parser::remove_dash(&node.global) + "/" + callee
+            } else {
+                callee.clone()
+            };
+            self.add_edge(id, file, EdgeKind::Call, &callee, None);
+        }
+        return id;
+    }
+
+    fn add_edge(
+        &mut self,
+        id: BlockId,
+        file: &str,
+        kind: EdgeKind,
+        label: &str,
+        condition: Option<String>,
+    ) {
+        let to = self.find(Some(file), label);
+        self.blocks[id].successors.push(Edge {
+            kind,
+            label: label.to_string(),
+            to,
+            condition,
+        });
+    }
+
+    /// ラベルのブロックを探す(`file`が与えられればそのファイルを優先する)
+    pub fn find(&self, file: Option<&str>, label: &str) -> Option<BlockId> {
+        if let Some(id) = file.and_then(|f| self.find_in_file(f, label)) {
+            return Some(id);
+        }
+        let mut files: Vec<_> = self.index.keys().collect();
+        files.sort();
+        return files
+            .into_iter()
+            .find_map(|file| self.find_in_file(file, label));
+    }
+
+    /// `file`の中だけでラベルのブロックを探す
+    pub fn find_in_file(&self, file: &str, label: &str) -> Option<BlockId> {
+        let labels = self.index.get(file)?;
+        return [
+            label.to_string(),
+            label.to_string() + ":",
+            label.to_string() + "::",
+        ]
+        .iter()
+        .find_map(|c| labels.get(c))
+        .cloned();
+    }
+
+    pub fn blocks(&self) -> &[BasicBlock] {
+        return &self.blocks;
+    }
+
+    pub fn block(&self, id: BlockId) -> &BasicBlock {
+        return &self.blocks[id];
+    }
+
+    pub fn successors(&self, id: BlockId) -> &[Edge] {
+        return &self.blocks[id].successors;
+    }
+
+    pub fn predecessors(&self, id: BlockId) -> &[BlockId] {
+        return &self.blocks[id].predecessors;
+    }
+
+    /// `starts`からcall以外の辺で辿り着けるブロック(見つけた順)
+    pub fn reachable(&self, starts: &[BlockId]) -> Vec<BlockId> {
+        let mut done = vec![false; self.blocks.len()];
+        let mut result = vec![];
+        let mut remaining: VecDeque<BlockId> = starts.iter().cloned().collect();
+        while let Some(id) = remaining.pop_front() {
+            if done[id] {
+                continue;
+            }
+            done[id] = true;
+            result.push(id);
+            remaining.extend(self.blocks[id].flow_successors().filter_map(|e| e.to));
+        }
+        return result;
+    }
+
+    /// ブロックに含まれる行(空行を除き、字下げは取り除く)
+    ///
+    /// `[rLCDC]`のように`symbols`のシンボルを含むオペランドには値を注記する
+    pub fn body(
+        &self,
+        asm: &HashMap<String, Nodes>,
+        symbols: &SymbolTable,
+        id: BlockId,
+    ) -> Vec<String> {
+        let block = &self.blocks[id];
+        let elements = match asm
+            .get(&block.file)
+            .and_then(|nodes| nodes.get(&block.node))
+        {
+            Some(node) => &node.elements[block.instructions.clone()],
+            None => &[],
+        };
+        return elements
+            .iter()
+            .filter(|e| e.text().trim() != "")
+            .map(|e| {
+                let text = e.text().trim();
+                let notes: Vec<String> = match e.instruction() {
+                    Some((_, operand1, operand2)) => [operand1, operand2]
+                        .iter()
+                        .filter_map(|o| {
+                            let value = symbols.annotate(o)?;
+                            let name = o.trim_start_matches('[').trim_end_matches(']');
+                            Some(format!("{} = {}", name, format_value(value)))
+                        })
+                        .collect(),
+                    None => vec![],
+                };
+                if notes.is_empty() {
+                    return text.to_string();
+                }
+                format!("{} ; {}", text, notes.join(", "))
+            })
+            .collect();
+    }
+
+    /// `starts`から辿り着けるブロックをGraphvizのdot形式にする
+    ///
+    /// ブロックは命令の一覧を持つrecordにしてファイル(SECTIONがあればSECTION)ごとにclusterにまとめ、
+    /// 条件分岐の成立/不成立の辺にはtrue/falseのラベルを付ける
+    pub fn to_dot(
+        &self,
+        asm: &HashMap<String, Nodes>,
+        symbols: &SymbolTable,
+        starts: &[BlockId],
+    ) -> String {
+        let reachable = self.reachable(starts);
+        let mut lines = vec![
+            "digraph cfg {".to_string(),
+            "    node [shape=record, fontname=\"monospace\"];".to_string(),
+        ];
+        for (i, &start) in starts.iter().enumerate() {
+            lines.push(format!(
+                "    st{} [shape=oval, label={}];",
+                i,
+                dot::quote(&self.blocks[start].label)
+            ));
+        }
+
+        let mut clusters: Vec<(&str, Option<&String>)> = reachable
+            .iter()
+            .map(|&id| (&self.blocks[id].file[..], self.blocks[id].section.as_ref()))
+            .collect();
+        clusters.sort();
+        clusters.dedup();
+        for (i, (file, section)) in clusters.into_iter().enumerate() {
+            let label = match section {
+                Some(section) => format!("SECTION \"{}\" ({})", section, file),
+                None => file.to_string(),
+            };
+            lines.push(format!("    subgraph cluster_{} {{", i));
+            lines.push(format!("        label={};", dot::quote(&label)));
+            for &id in reachable.iter().filter(|&&id| {
+                self.blocks[id].file == file && self.blocks[id].section.as_ref() == section
+            }) {
+                lines.push(format!(
+                    "        b{} [label=\"{{{}|{}}}\"];",
+                    id,
+                    dot::record_field(&self.blocks[id].label),
+                    dot::record_field(&self.body(asm, symbols, id).join("\n"))
+                ));
+            }
+            lines.push("    }".to_string());
+        }
+
+        let mut edges = vec![];
+        for (i, &start) in starts.iter().enumerate() {
+            edges.push(format!("    st{} -> b{};", i, start));
+        }
+        let (flow, missing) = self.flow_edges(&reachable);
+        for label in &missing {
+            lines.push(format!("    {} [shape=plaintext];", dot::quote(label)));
+        }
+        if flow.iter().any(|e| e.to == Target::End) {
+            lines.push("    e [shape=oval, label=\"end\"];".to_string());
+        }
+        for edge in flow {
+            let to = match edge.to {
+                Target::Block(to) => format!("b{}", to),
+                Target::Missing(i) => dot::quote(&missing[i]),
+                Target::End => "e".to_string(),
+            };
+            let label = match edge.label {
+                Some(label) => format!(" [label=\"{}\"]", label),
+                None => "".to_string(),
+            };
+            edges.push(format!("    b{} -> {}{};", edge.from, to, label));
+        }
+        lines.extend(edges);
+        lines.push("}".to_string());
+        return lines.join("\n") + "\n";
+    }
+
+    /// `starts`から辿り着けるブロックをMermaidの`flowchart TD`にする
+    ///
+    /// 条件分岐で終わるブロックはひし形にし、グローバルラベルごとにsubgraphにまとめる
+    pub fn to_mermaid(
+        &self,
+        asm: &HashMap<String, Nodes>,
+        symbols: &SymbolTable,
+        starts: &[BlockId],
+    ) -> String {
+        let reachable = self.reachable(starts);
+        let mut lines = vec!["flowchart TD".to_string()];
+        for (i, &start) in starts.iter().enumerate() {
+            lines.push(format!(
+                "    st{}([\"{}\"])",
+                i,
+                mermaid::escape(&self.blocks[start].label)
+            ));
+        }
+
+        let mut globals: Vec<(&str, String)> = reachable
+            .iter()
+            .map(|&id| {
+                let block = &self.blocks[id];
+                (&block.file[..], parser::remove_dash(&block.global))
+            })
+            .collect();
+        globals.sort();
+        globals.dedup();
+        for (i, (file, global)) in globals.iter().enumerate() {
+            lines.push(format!(
+                "    subgraph g{} [\"{}\"]",
+                i,
+                mermaid::escape(&format!("[{}] {}", file, global))
+            ));
+            for &id in &reachable {
+                let block = &self.blocks[id];
+                if block.file != *file || parser::remove_dash(&block.global) != *global {
+                    continue;
+                }
+                let mut text = vec![block.label.clone()];
+                text.extend(self.body(asm, symbols, id));
+                let text = mermaid::escape(&text.join("\n"));
+                if block.is_conditional() {
+                    lines.push(format!("        b{}{{\"{}\"}}", id, text));
+                } else {
+                    lines.push(format!("        b{}[\"{}\"]", id, text));
+                }
+            }
+            lines.push("    end".to_string());
+        }
+
+        let mut edges = vec![];
+        for (i, &start) in starts.iter().enumerate() {
+            edges.push(format!("    st{} --> b{}", i, start));
+        }
+        let (flow, missing) = self.flow_edges(&reachable);
+        for (i, label) in missing.iter().enumerate() {
+            lines.push(format!(
+                "    u{}[\"{}\"]",
+                i,
+                mermaid::escape(&format!("{}\nnot found", label))
+            ));
+        }
+        if flow.iter().any(|e| e.to == Target::End) {
+            lines.push("    e([\"end\"])".to_string());
+        }
+        for edge in flow {
+            let to = match edge.to {
+                Target::Block(to) => format!("b{}", to),
+                Target::Missing(i) => format!("u{}", i),
+                Target::End => "e".to_string(),
+            };
+            let arrow = match edge.label {
+                Some(label) => format!("-->|\"{}\"|", label),
+                None => "-->".to_string(),
+            };
+            edges.push(format!("    b{} {} {}", edge.from, arrow, to));
+        }
+        lines.extend(edges);
+        return lines.join("\n") + "\n";
+    }
+
+    /// `starts`から辿り着けるブロックを階層型に配置したSVGにする
+    pub fn to_svg(
+        &self,
+        asm: &HashMap<String, Nodes>,
+        symbols: &SymbolTable,
+        starts: &[BlockId],
+    ) -> String {
+        let reachable = self.reachable(starts);
+        let mut nodes = vec![];
+        let mut edges = vec![];
+        for &start in starts {
+            nodes.push(svg::Node {
+                kind: svg::NodeKind::Start,
+                lines: vec![self.blocks[start].label.clone()],
+            });
+        }
+        // ブロックからノードの添字への対応表
+        let mut index = HashMap::new();
+        for &id in &reachable {
+            let block = &self.blocks[id];
+            let mut lines = vec![block.label.clone()];
+            lines.extend(
+                self.body(asm, symbols, id)
+                    .iter()
+                    .map(|l| l.replace('\t', "    ")),
+            );
+            let kind = if block.is_conditional() {
+                svg::NodeKind::Conditional
+            } else {
+                svg::NodeKind::Block
+            };
+            index.insert(id, nodes.len());
+            nodes.push(svg::Node { kind, lines });
+        }
+        for (i, &start) in starts.iter().enumerate() {
+            edges.push(svg::Edge {
+                from: i,
+                to: index[&start],
+                label: None,
+            });
+        }
+
+        // 見つからないラベルと終端は最初に出てきたときにノードを足す
+        let (flow, missing) = self.flow_edges(&reachable);
+        let mut targets: HashMap<Target, usize> = HashMap::new();
+        for edge in flow {
+            let to = match edge.to {
+                Target::Block(to) => index[&to],
+                target => *targets.entry(target).or_insert_with(|| {
+                    let (kind, lines) = match target {
+                        Target::Missing(i) => (
+                            svg::NodeKind::Missing,
+                            vec![missing[i].clone(), "(not found)".to_string()],
+                        ),
+                        _ => (svg::NodeKind::End, vec!["end".to_string()]),
+                    };
+                    nodes.push(svg::Node { kind, lines });
+                    nodes.len() - 1
+                }),
+            };
+            edges.push(svg::Edge {
+                from: index[&edge.from],
+                to,
+                label: edge.label,
+            });
+        }
+        return svg::render(&nodes, &edges);
+    }
+
+    /// `blocks`から出るcall以外の辺を図に描く順に並べる
+    ///
+    /// 後続のないブロックは終端へ進め、条件分岐の辺には成立/不成立のラベルを付ける。
+    /// 見つからないラベルは出てきた順に並べて一緒に返す
+    fn flow_edges(&self, blocks: &[BlockId]) -> (Vec<FlowEdge>, Vec<String>) {
+        let mut edges = vec![];
+        let mut missing: Vec<String> = vec![];
+        for &id in blocks {
+            let block = &self.blocks[id];
+            let conditional = block.is_conditional();
+            for edge in block.flow_successors() {
+                let to = match (edge.kind, edge.to) {
+                    (EdgeKind::Return, _) => Target::End,
+                    (_, Some(to)) => Target::Block(to),
+                    (_, None) => match missing.iter().position(|l| *l == edge.label) {
+                        Some(i) => Target::Missing(i),
+                        None => {
+                            missing.push(edge.label.clone());
+                            Target::Missing(missing.len() - 1)
+                        }
+                    },
+                };
+                let label = match (conditional, &edge.condition) {
+                    (true, Some(condition)) => Some(format!("true ({})", condition)),
+                    (true, None) => Some("false".to_string()),
+                    (false, _) => None,
+                };
+                edges.push(FlowEdge {
+                    from: id,
+                    to,
+                    label,
+                });
+            }
+            if block.flow_successors().next().is_none() {
+                edges.push(FlowEdge {
+                    from: id,
+                    to: Target::End,
+                    label: None,
+                });
+            }
+        }
+        return (edges, missing);
+    }
+}
+
+/// 注記する値(アドレスになりうる値は16進数にする)
+fn format_value(value: i64) -> String {
+    if (0..=0xFFFF).contains(&value) {
+        return format!("${:04X}", value);
+    }
+    return format!("{}", value);
+}
+
+fn parser_is_call(op: &str) -> bool {
+    return super::variable::opcode::is_call(&op.to_ascii_uppercase());
+}
+
+fn last_instruction(node: &Node) -> Option<(&str, &str, &str)> {
+    return node.elements.iter().rev().find_map(|e| e.instruction());
+}
+
+/// ノードの最後の命令のオペランドのほかに辿れる飛び先
+fn last_alternatives(node: &Node) -> &[String] {
+    return node
+        .elements
+        .iter()
+        .rev()
+        .find(|e| e.instruction().is_some())
+        .map_or(&[], |e| e.alternatives());
+}
+
+/// ノードの最後の条件分岐の条件
+fn last_condition(node: &Node) -> Option<String> {
+    return last_instruction(node).and_then(|(_, operand1, _)| condition(operand1));
+}
+
+/// ノードが無条件のjp/jrで終わるか
+fn ends_with_jump(node: &Node) -> bool {
+    return match last_instruction(node) {
+        Some((op, operand1, _)) => {
+            super::variable::opcode::is_jump(&op.to_ascii_uppercase())
+                && condition(operand1).is_none()
+        }
+        None => false,
+    };
+}
+
+/// テスト用に解析したソースとシンボル
+#[cfg(test)]
+fn sample() -> (HashMap<String, Nodes>, SymbolTable) {
+    let text = "LOOPS EQU 3
+SECTION \"Code\", ROM0
+start:
+\tld a, LOOPS
+.loop
+\tdec a
+\tjr nz, .loop
+\tcall sub
+\tret
+\tnop
+sub:
+\tret z
+\tinc a
+\tjp start
+";
+    let (asm, _, mut p) = parser::parse_snippet(text);
+    return (asm, p.take_symbols());
+}
+
+#[test]
+fn test_build_cfg() {
+    let (asm, _) = sample();
+    let cfg = Cfg::build(&asm);
+
+    let start = cfg.find(None, "start").unwrap();
+    let lp = cfg.find(None, "start/.loop").unwrap();
+    assert_eq!(cfg.successors(start)[0].kind, EdgeKind::Fallthrough);
+    assert_eq!(cfg.successors(start)[0].to, Some(lp));
+    assert!(cfg.block(lp).is_conditional());
+    assert!(cfg.predecessors(lp).contains(&lp));
+
+    // jr nzの後ろのブロックはcallとretを含み、retの後ろのnopは別のブロックになる
+    let after = cfg.find(None, "start/.loop'").unwrap();
+    let kinds: Vec<EdgeKind> = cfg.successors(after).iter().map(|e| e.kind).collect();
+    assert_eq!(kinds, vec![EdgeKind::Return, EdgeKind::Call]);
+    let sub = cfg.find(None, "sub").unwrap();
+    assert_eq!(cfg.successors(after)[1].to, Some(sub));
+    assert!(cfg.find(None, "start/.loop'#1").is_some());
+
+    // ret zは条件付きのReturnと次のブロックへのFallthroughを持つ
+    assert!(cfg.block(sub).is_conditional());
+    let rest = cfg.find(None, "sub#1").unwrap();
+    assert_eq!(cfg.successors(rest)[0].kind, EdgeKind::Taken);
+    assert_eq!(cfg.successors(rest)[0].to, Some(start));
+    assert_eq!(cfg.blocks().len(), 9);
+}
+
+#[test]
+fn test_to_dot() {
+    let (asm, _) = sample();
+    let cfg = Cfg::build(&asm);
+    let start = cfg.find(None, "start").unwrap();
+    let lp = cfg.find(None, "start/.loop").unwrap();
+    let after = cfg.find(None, "start/.loop'").unwrap();
+    let sub = cfg.find(None, "sub").unwrap();
+
+    let dot = cfg.to_dot(&asm, &SymbolTable::default(), &[start]);
+    assert!(dot.contains(&format!("b{} -> b{} [label=\"true (nz)\"];", lp, lp)));
+    assert!(dot.contains(&format!("b{} -> b{} [label=\"false\"];", lp, after)));
+    assert!(dot.contains(&format!(
+        "b{} [label=\"{{start/.loop\\l|dec a\\ljr nz, .loop\\l}}\"];",
+        lp
+    )));
+    assert!(dot.contains(&format!("b{} -> e;", after)));
+    assert!(!dot.contains(&format!("b{} ", sub)));
+}
+
+#[test]
+fn test_to_mermaid() {
+    let (asm, _) = sample();
+    let cfg = Cfg::build(&asm);
+    let start = cfg.find(None, "start").unwrap();
+    let lp = cfg.find(None, "start/.loop").unwrap();
+
+    let mermaid = cfg.to_mermaid(&asm, &SymbolTable::default(), &[start]);
+    assert!(mermaid.starts_with("flowchart TD\n"));
+    assert!(mermaid.contains(&format!(
+        "b{}{{\"start/.loop<br/>dec a<br/>jr nz, .loop\"}}",
+        lp
+    )));
+    assert!(mermaid.contains(&format!("b{} -->|\"true (nz)\"| b{}", lp, lp)));
+    assert!(mermaid.contains("subgraph g0 [\"[main.asm] start\"]"));
+}
+
+#[test]
+fn test_to_svg() {
+    let (asm, _) = sample();
+    let cfg = Cfg::build(&asm);
+    let start = cfg.find(None, "start").unwrap();
+
+    let svg = cfg.to_svg(&asm, &SymbolTable::default(), &[start]);
+    assert!(svg.contains("<text x=\"") && svg.contains(">dec a</text>"));
+    assert_eq!(svg.matches("<rect").count(), 5);
+}
+
+#[test]
+fn test_body_annotation() {
+    let (asm, symbols) = sample();
+    let cfg = Cfg::build(&asm);
+    let start = cfg.find(None, "start").unwrap();
+    assert_eq!(
+        cfg.body(&asm, &symbols, start),
+        vec!["ld a, LOOPS ; LOOPS = $0003"]
+    );
+}
+
+#[test]
+fn test_section_cluster() {
+    let (asm, _) = sample();
+    let cfg = Cfg::build(&asm);
+    let start = cfg.find(None, "start").unwrap();
+    let dot = cfg.to_dot(&asm, &SymbolTable::default(), &[start]);
+    assert!(dot.contains("label=\"SECTION \\\"Code\\\" (main.asm)\";"));
+}
+
+#[test]
+fn test_uppercase_terminator() {
+    let text = "start:
+\tDEC A
+\tJR NZ, start
+\tRET Z
+\tJP HL
+";
+    let (asm, _, _) = parser::parse_snippet(text);
+    let cfg = Cfg::build(&asm);
+
+    // 大文字の条件やhlも小文字と同じに扱う
+    let start = cfg.find(None, "start").unwrap();
+    assert!(cfg.block(start).is_conditional());
+    let taken = cfg
+        .successors(start)
+        .iter()
+        .find(|e| e.kind == EdgeKind::Taken)
+        .unwrap();
+    assert_eq!(
+        (taken.kind, taken.to, taken.condition.as_deref()),
+        (EdgeKind::Taken, Some(start), Some("nz"))
+    );
+    let after = cfg.find(None, "start'").unwrap();
+    let edge = &cfg.successors(after)[0];
+    assert_eq!(
+        (edge.kind, edge.condition.as_deref()),
+        (EdgeKind::Return, Some("z"))
+    );
+    let rest = cfg.find(None, "start'#1").unwrap();
+    let edge = &cfg.successors(rest)[0];
+    assert_eq!((edge.kind, &edge.label[..]), (EdgeKind::Taken, "hl"));
+    assert_eq!(cfg.successors(rest).len(), 1);
+}
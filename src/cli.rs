@@ -0,0 +1,256 @@
+use flowchartgb::{Format, Graph};
+use std::fmt;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "usage:
+    flowchartgb rom <ROM> [--entry <LABEL>]... [-o <DIR>] [-f <FORMAT>] [-g <GRAPH>] [--split] [--per-entry]
+    flowchartgb asm <ASM> [--entry <LABEL>]... [--entry-all-exported] [-D <NAME[=VALUE]>]... [-o <DIR>] [-f <FORMAT>] [-g <GRAPH>] [--split] [--per-entry]
+    flowchartgb --gui [LABEL] [-o <DIR>] [-f <FORMAT>] [-g <GRAPH>]
+
+options:
+    -e, --entry <LABEL>     label for the starting point, can be given several
+                            times (default: every discovered entry point)
+    --entry-all-exported    start from every label exported with :: or EXPORT
+                            (asm only)
+    --per-entry             write the charts of each starting point into
+                            <DIR>/<LABEL>/ instead of combining them
+    -o, --output <DIR>      output directory (default: directory of the input file)
+    -f, --format <FORMAT>   output format: flowchart, json, dot, mermaid,
+                            html, svg (default: flowchart)
+    -g, --graph <GRAPH>     graph to output: cfg, calls (default: cfg)
+    --split                 write one flowchart per routine into <DIR>/<NAME>/
+                            with an index.md (flowchart format only)
+    -D, --define <NAME[=VALUE]>
+                            define a string symbol like rgbasm for IF/ELIF
+                            (asm only, VALUE defaults to 1)
+    --gui                   select the input file with a file dialog
+    -h, --help              print this message";
+
+/// 終了コード
+pub mod exit_code {
+    pub const SUCCESS: i32 = 0;
+    pub const FAILURE: i32 = 1;
+    pub const USAGE: i32 = 2;
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Command {
+    Rom,
+    Asm,
+    Gui,
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub command: Command,
+    pub input: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub format: Format,
+    pub graph: Graph,
+    /// 開始ラベル(空ならasmでは探し、romでは見つかったエントリポイントにする)
+    pub entries: Vec<String>,
+    /// `::`やEXPORTで公開したラベル全てから始める
+    pub all_exported: bool,
+    /// 開始ラベルごとに出力を分ける
+    pub per_entry: bool,
+    /// `-D NAME=value`で定義するシンボル
+    pub defines: Vec<(String, String)>,
+    /// ルーチンごとに図を分けて出力する
+    pub split: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct UsageError(pub String);
+
+impl fmt::Display for UsageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// コマンドライン引数をパースする(argsにはプログラム名を含まない)
+pub fn parse_args(args: &[String]) -> Result<Options, UsageError> {
+    let mut command = None;
+    let mut positional = vec![];
+    let mut output = None;
+    let mut format = Format::Flowchart;
+    let mut graph = Graph::Cfg;
+    let mut entries = vec![];
+    let mut all_exported = false;
+    let mut per_entry = false;
+    let mut defines = vec![];
+    let mut split = false;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-h" | "--help" => command = Some(Command::Help),
+            "--gui" => command = command.or(Some(Command::Gui)),
+            "--split" => split = true,
+            "-o" | "--output" => output = Some(PathBuf::from(value_of(arg, iter.next())?)),
+            "-e" | "--entry" => entries.push(value_of(arg, iter.next())?),
+            "--entry-all-exported" => all_exported = true,
+            "--per-entry" => per_entry = true,
+            "-f" | "--format" => {
+                let value = value_of(arg, iter.next())?;
+                format = parse_format(&value)
+                    .ok_or_else(|| UsageError(format!("unknown format: {}", value)))?;
+            }
+            "-g" | "--graph" => {
+                let value = value_of(arg, iter.next())?;
+                graph = parse_graph(&value)
+                    .ok_or_else(|| UsageError(format!("unknown graph: {}", value)))?;
+            }
+            "-D" | "--define" => defines.push(parse_define(&value_of(arg, iter.next())?)?),
+            a if a.starts_with("-D") => defines.push(parse_define(&a[2..])?),
+            a if a.starts_with('-') && a.len() > 1 => {
+                return Err(UsageError(format!("unknown option: {}", a)));
+            }
+            "rom" if command.is_none() && positional.is_empty() => command = Some(Command::Rom),
+            "asm" if command.is_none() && positional.is_empty() => command = Some(Command::Asm),
+            a => positional.push(a.to_string()),
+        }
+    }
+
+    let command = command.ok_or_else(|| UsageError("no subcommand given".to_string()))?;
+    let mut positional = positional.into_iter();
+    let input = match command {
+        Command::Rom | Command::Asm => {
+            Some(PathBuf::from(positional.next().ok_or_else(|| {
+                UsageError("no input file given".to_string())
+            })?))
+        }
+        Command::Gui => {
+            // GUIモードでは従来通り位置引数を開始ラベルとして受け付ける
+            if entries.is_empty() {
+                entries.extend(positional.next());
+            }
+            None
+        }
+        Command::Help => None,
+    };
+    if let Some(extra) = positional.next() {
+        return Err(UsageError(format!("unexpected argument: {}", extra)));
+    }
+    if command == Command::Rom && (!defines.is_empty() || all_exported) {
+        return Err(UsageError(
+            "-D and --entry-all-exported are only supported for asm files".to_string(),
+        ));
+    }
+    if split && (format != Format::Flowchart || graph != Graph::Cfg) {
+        return Err(UsageError(
+            "--split is only supported for the flowchart format".to_string(),
+        ));
+    }
+
+    Ok(Options {
+        command,
+        input,
+        output,
+        format,
+        graph,
+        entries,
+        all_exported,
+        per_entry,
+        defines,
+        split,
+    })
+}
+
+fn parse_format(s: &str) -> Option<Format> {
+    match s {
+        "flowchart" => Some(Format::Flowchart),
+        "json" => Some(Format::Json),
+        "dot" => Some(Format::Dot),
+        "mermaid" => Some(Format::Mermaid),
+        "html" => Some(Format::Html),
+        "svg" => Some(Format::Svg),
+        _ => None,
+    }
+}
+
+fn parse_graph(s: &str) -> Option<Graph> {
+    match s {
+        "cfg" => Some(Graph::Cfg),
+        "calls" => Some(Graph::Calls),
+        _ => None,
+    }
+}
+
+/// `NAME=value`を分ける(valueを省略すると1)
+fn parse_define(s: &str) -> Result<(String, String), UsageError> {
+    let (name, value) = match s.find('=') {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, "1"),
+    };
+    if name.is_empty() {
+        return Err(UsageError(format!("invalid definition: {}", s)));
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+fn value_of(option: &str, value: Option<&String>) -> Result<String, UsageError> {
+    match value {
+        Some(v) => Ok(v.to_string()),
+        None => Err(UsageError(format!("{} requires a value", option))),
+    }
+}
+
+#[cfg(test)]
+fn to_args(s: &str) -> Vec<String> {
+    s.split_whitespace().map(|a| a.to_string()).collect()
+}
+
+#[test]
+fn test_parse_args() {
+    let opts = parse_args(&to_args("asm main.asm --entry start -o out -f json")).unwrap();
+    assert_eq!(opts.command, Command::Asm);
+    assert_eq!(opts.input, Some(PathBuf::from("main.asm")));
+    assert_eq!(opts.entries, ["start"]);
+    assert_eq!(opts.output, Some(PathBuf::from("out")));
+    assert_eq!(opts.format, Format::Json);
+    assert!(opts.defines.is_empty());
+    assert!(!opts.split);
+
+    let opts = parse_args(&to_args("asm main.asm -e start -D DEBUG -DVERSION=2")).unwrap();
+    assert_eq!(
+        opts.defines,
+        vec![
+            ("DEBUG".to_string(), "1".to_string()),
+            ("VERSION".to_string(), "2".to_string())
+        ]
+    );
+
+    let opts = parse_args(&to_args("rom game.gb")).unwrap();
+    assert_eq!(opts.command, Command::Rom);
+    assert_eq!(opts.format, Format::Flowchart);
+    assert_eq!(opts.graph, Graph::Cfg);
+
+    let opts = parse_args(&to_args("rom game.gb -g calls -f dot")).unwrap();
+    assert_eq!(opts.graph, Graph::Calls);
+    assert_eq!(opts.format, Format::Dot);
+
+    assert!(parse_args(&to_args("rom game.gb --split")).unwrap().split);
+    assert!(parse_args(&to_args("rom game.gb --split -f dot")).is_err());
+
+    let opts = parse_args(&to_args("--gui start")).unwrap();
+    assert_eq!(opts.command, Command::Gui);
+    assert_eq!(opts.entries, ["start"]);
+
+    let opts = parse_args(&to_args("asm main.asm -e init -e vblank --per-entry")).unwrap();
+    assert_eq!(opts.entries, ["init", "vblank"]);
+    assert!(opts.per_entry);
+    let opts = parse_args(&to_args("asm main.asm --entry-all-exported")).unwrap();
+    assert!(opts.entries.is_empty());
+    assert!(opts.all_exported);
+
+    assert!(parse_args(&to_args("asm main.asm -e")).is_err());
+    assert!(parse_args(&to_args("rom")).is_err());
+    assert!(parse_args(&to_args("rom game.gb -f png")).is_err());
+    assert!(parse_args(&to_args("rom game.gb -g tree")).is_err());
+    assert!(parse_args(&[]).is_err());
+    assert!(parse_args(&to_args("asm main.asm -e start -D =1")).is_err());
+    assert!(parse_args(&to_args("rom game.gb -D DEBUG")).is_err());
+    assert!(parse_args(&to_args("rom game.gb --entry-all-exported")).is_err());
+}
@@ -0,0 +1,124 @@
+extern crate serde;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// 解析中に見つかった問題
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// 問題のあるファイル(ROMのヘッダなどファイルに紐づかない場合はNone)
+    pub file: Option<String>,
+    /// 1始まりの行番号
+    pub line: Option<usize>,
+    /// 1始まりの桁
+    #[serde(default)]
+    pub column: Option<usize>,
+}
+
+/// ソース上の位置
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Span {
+    pub file: String,
+    /// 1始まりの行番号(マクロを展開した行は呼び出した行)
+    pub line: usize,
+    /// 1始まりの桁(タブも1文字と数える)
+    pub column: usize,
+}
+
+impl Diagnostic {
+    pub fn warning(message: &str) -> Self {
+        return Self {
+            severity: Severity::Warning,
+            message: message.to_string(),
+            file: None,
+            line: None,
+            column: None,
+        };
+    }
+
+    /// 問題のある場所を設定する
+    pub fn at(mut self, file: &str, line: Option<usize>) -> Self {
+        self.file = Some(file.to_string());
+        self.line = line;
+        return self;
+    }
+
+    pub fn at_span(mut self, span: &Span) -> Self {
+        self.file = Some(span.file.clone());
+        self.line = Some(span.line);
+        self.column = Some(span.column);
+        return self;
+    }
+
+    /// コンパイラのように場所とソースの行を付けて整形する
+    ///
+    /// `source`は問題のある行のテキスト
+    pub fn render(&self, source: Option<&str>) -> String {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        let mut lines = vec![format!("{}: {}", severity, self.message)];
+        let file = match &self.file {
+            Some(file) => file,
+            None => return lines.join("\n"),
+        };
+        let location = match (self.line, self.column) {
+            (Some(line), Some(column)) => format!("{}:{}:{}", file, line, column),
+            (Some(line), None) => format!("{}:{}", file, line),
+            _ => file.clone(),
+        };
+        lines.push(format!("  --> {}", location));
+        if let (Some(line), Some(source)) = (self.line, source) {
+            let number = line.to_string();
+            let pad = " ".repeat(number.len());
+            lines.push(format!("{} |", pad));
+            lines.push(format!("{} | {}", number, source.replace('\t', "    ")));
+            if let Some(column) = self.column {
+                // 問題のある桁から行末(コメントを除く)までに印を付ける
+                let before: String = source.chars().take(column - 1).collect();
+                let after: String = source.chars().skip(column - 1).collect();
+                let code = after.split(';').next().unwrap_or("").trim_end();
+                lines.push(format!(
+                    "{} | {}{}",
+                    pad,
+                    " ".repeat(before.replace('\t', "    ").chars().count()),
+                    "^".repeat(code.replace('\t', "    ").chars().count().max(1))
+                ));
+            }
+        }
+        return lines.join("\n");
+    }
+}
+
+#[test]
+fn test_render_diagnostic() {
+    let span = Span {
+        file: "main.asm".to_string(),
+        line: 12,
+        column: 2,
+    };
+    let diagnostic = Diagnostic::warning("undefined label foo").at_span(&span);
+    assert_eq!(
+        diagnostic.render(Some("\tjp foo ; go")),
+        "warning: undefined label foo
+  --> main.asm:12:2
+   |
+12 |     jp foo ; go
+   |     ^^^^^^"
+    );
+    assert_eq!(
+        Diagnostic {
+            severity: Severity::Error,
+            ..Diagnostic::warning("no header")
+        }
+        .render(None),
+        "error: no header"
+    );
+}
@@ -20,15 +20,10 @@ fn test_open_file_dialog() {
     let mut result = false;
     let path = Path::new(&file_path);
     let extension = path.extension();
-    match extension {
-        Some(ext) => {
-            if ext == "gb" || ext == "gbc" {
-                result = true;
-            } else if ext == "asm" {
-                result = true;
-            }
+    if let Some(ext) = extension {
+        if ext == "gb" || ext == "gbc" || ext == "asm" {
+            result = true;
         }
-        None => {}
     }
     assert!(result);
 }
@@ -1,28 +1,838 @@
-use std::process::Command;
-
-pub fn disassemble(gb_file_path: &str) {
-    let output = if cfg!(target_os = "windows") {
-        let cmd = format!("python ./mgbdis/mgbdis.py {}", gb_file_path);
-        // println!("{}", &cmd);
-        Command::new("cmd")
-            .args(&["/C", &cmd])
-            .output()
-            .expect("failed to execute process")
-    } else {
-        let cmd = format!("python3 ./mgbdis/mgbdis.py {}", gb_file_path);
-        // println!("{}", &cmd);
-        Command::new("sh")
-            .arg("-c")
-            .arg(cmd)
-            .output()
-            .expect("failed to execute process")
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::diagnostic::Diagnostic;
+use super::error::{Error, Result};
+use super::header::Header;
+use super::mbc::{BankedAddress, Mbc, BANK_SIZE};
+use super::parser::{Nodes, Parser};
+
+const R: [&str; 8] = ["b", "c", "d", "e", "h", "l", "[hl]", "a"];
+const RP: [&str; 4] = ["bc", "de", "hl", "sp"];
+const RP2: [&str; 4] = ["bc", "de", "hl", "af"];
+const CC: [&str; 4] = ["nz", "z", "nc", "c"];
+const ALU: [(&str, bool); 8] = [
+    ("add", true),
+    ("adc", true),
+    ("sub", false),
+    ("sbc", true),
+    ("and", false),
+    ("xor", false),
+    ("or", false),
+    ("cp", false),
+];
+const ROT: [&str; 8] = ["rlc", "rrc", "rl", "rr", "sla", "sra", "swap", "srl"];
+
+/// mgbdisと同じ既定のシンボル
+const DEFAULT_SYMBOLS: [(u16, &str); 14] = [
+    (0x0000, "RST_00"),
+    (0x0008, "RST_08"),
+    (0x0010, "RST_10"),
+    (0x0018, "RST_18"),
+    (0x0020, "RST_20"),
+    (0x0028, "RST_28"),
+    (0x0030, "RST_30"),
+    (0x0038, "RST_38"),
+    (0x0040, "VBlankInterrupt"),
+    (0x0048, "LCDCInterrupt"),
+    (0x0050, "TimerOverflowInterrupt"),
+    (0x0058, "SerialTransferCompleteInterrupt"),
+    (0x0060, "JoypadTransitionInterrupt"),
+    (0x0100, "Boot"),
+];
+
+/// ヘッダ領域(ロゴ～グローバルチェックサム)はデータとして扱う
+const HEADER_DATA: std::ops::Range<usize> = 0x0104..0x0150;
+
+const HARDWARE_LABELS: [(u16, &str); 58] = [
+    (0xFF00, "rP1"),
+    (0xFF01, "rSB"),
+    (0xFF02, "rSC"),
+    (0xFF04, "rDIV"),
+    (0xFF05, "rTIMA"),
+    (0xFF06, "rTMA"),
+    (0xFF07, "rTAC"),
+    (0xFF0F, "rIF"),
+    (0xFF10, "rNR10"),
+    (0xFF11, "rNR11"),
+    (0xFF12, "rNR12"),
+    (0xFF13, "rNR13"),
+    (0xFF14, "rNR14"),
+    (0xFF16, "rNR21"),
+    (0xFF17, "rNR22"),
+    (0xFF18, "rNR23"),
+    (0xFF19, "rNR24"),
+    (0xFF1A, "rNR30"),
+    (0xFF1B, "rNR31"),
+    (0xFF1C, "rNR32"),
+    (0xFF1D, "rNR33"),
+    (0xFF1E, "rNR34"),
+    (0xFF20, "rNR41"),
+    (0xFF21, "rNR42"),
+    (0xFF22, "rNR43"),
+    (0xFF23, "rNR44"),
+    (0xFF24, "rNR50"),
+    (0xFF25, "rNR51"),
+    (0xFF26, "rNR52"),
+    (0xFF40, "rLCDC"),
+    (0xFF41, "rSTAT"),
+    (0xFF42, "rSCY"),
+    (0xFF43, "rSCX"),
+    (0xFF44, "rLY"),
+    (0xFF45, "rLYC"),
+    (0xFF46, "rDMA"),
+    (0xFF47, "rBGP"),
+    (0xFF48, "rOBP0"),
+    (0xFF49, "rOBP1"),
+    (0xFF4A, "rWY"),
+    (0xFF4B, "rWX"),
+    (0xFF4D, "rKEY1"),
+    (0xFF4F, "rVBK"),
+    (0xFF51, "rHDMA1"),
+    (0xFF52, "rHDMA2"),
+    (0xFF53, "rHDMA3"),
+    (0xFF54, "rHDMA4"),
+    (0xFF55, "rHDMA5"),
+    (0xFF56, "rRP"),
+    (0xFF68, "rBCPS"),
+    (0xFF69, "rBCPD"),
+    (0xFF6A, "rOCPS"),
+    (0xFF6B, "rOCPD"),
+    (0xFF70, "rSVBK"),
+    (0xFF76, "rPCM12"),
+    (0xFF77, "rPCM34"),
+    (0xFFFF, "rIE"),
+    (0xFF50, "rBOOT"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    /// レジスタや条件、`[hl+]`などの固定表記
+    Fixed(&'static str),
+    Imm8(u8),
+    Imm16(u16),
+    /// `sp+e8`
+    SpOffset(i8),
+    /// `[a16]`
+    Mem(u16),
+    /// `ldh [a8]`
+    High(u8),
+    /// `jp`/`jr`/`call`の飛び先
+    Target(u16),
+    /// `rst`のベクタ
+    Vector(u8),
+    /// `bit`/`res`/`set`のビット番号
+    Bit(u8),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub address: u16,
+    pub length: usize,
+    /// 未定義命令は`db`になる
+    pub mnemonic: &'static str,
+    pub operands: Vec<Operand>,
+}
+
+impl Instruction {
+    /// `jp`/`jr`/`call`/`rst`の飛び先アドレス
+    pub fn target(&self) -> Option<u16> {
+        self.operands.iter().find_map(|o| match o {
+            Operand::Target(addr) => Some(*addr),
+            Operand::Vector(v) => Some(*v as u16),
+            _ => None,
+        })
+    }
+}
+
+fn byte(rom: &[u8], offset: usize) -> u8 {
+    return *rom.get(offset).unwrap_or(&0);
+}
+
+fn word(rom: &[u8], offset: usize) -> u16 {
+    return byte(rom, offset) as u16 | (byte(rom, offset + 1) as u16) << 8;
+}
+
+/// `rom[offset]`から1命令をデコードする(`address`はその命令のCPUから見たアドレス)
+pub fn decode(rom: &[u8], offset: usize, address: u16) -> Instruction {
+    let op = byte(rom, offset);
+    let (x, y, z) = (op >> 6, (op >> 3) & 7, op & 7);
+    let (p, q) = ((y >> 1) as usize, y & 1);
+    let (y, z) = (y as usize, z as usize);
+    let d8 = || byte(rom, offset + 1);
+    let d16 = || word(rom, offset + 1);
+    let rel = || address.wrapping_add(2).wrapping_add(d8() as i8 as u16);
+
+    use Operand::*;
+    let (mnemonic, operands, length): (&'static str, Vec<Operand>, usize) = match (x, z) {
+        (0, 0) => match y {
+            0 => ("nop", vec![], 1),
+            1 => ("ld", vec![Mem(d16()), Fixed("sp")], 3),
+            2 => ("stop", vec![], 2),
+            3 => ("jr", vec![Target(rel())], 2),
+            _ => ("jr", vec![Fixed(CC[y - 4]), Target(rel())], 2),
+        },
+        (0, 1) if q == 0 => ("ld", vec![Fixed(RP[p]), Imm16(d16())], 3),
+        (0, 1) => ("add", vec![Fixed("hl"), Fixed(RP[p])], 1),
+        (0, 2) => {
+            let mem = ["[bc]", "[de]", "[hl+]", "[hl-]"][p];
+            if q == 0 {
+                ("ld", vec![Fixed(mem), Fixed("a")], 1)
+            } else {
+                ("ld", vec![Fixed("a"), Fixed(mem)], 1)
+            }
+        }
+        (0, 3) => (["inc", "dec"][q as usize], vec![Fixed(RP[p])], 1),
+        (0, 4) => ("inc", vec![Fixed(R[y])], 1),
+        (0, 5) => ("dec", vec![Fixed(R[y])], 1),
+        (0, 6) => ("ld", vec![Fixed(R[y]), Imm8(d8())], 2),
+        (0, _) => (
+            ["rlca", "rrca", "rla", "rra", "daa", "cpl", "scf", "ccf"][y],
+            vec![],
+            1,
+        ),
+        (1, 6) if y == 6 => ("halt", vec![], 1),
+        (1, _) => ("ld", vec![Fixed(R[y]), Fixed(R[z])], 1),
+        (2, _) => alu(y, Fixed(R[z]), 1),
+        (_, 0) => match y {
+            0..=3 => ("ret", vec![Fixed(CC[y])], 1),
+            4 => ("ldh", vec![High(d8()), Fixed("a")], 2),
+            5 => ("add", vec![Fixed("sp"), SpOffset(d8() as i8)], 2),
+            6 => ("ldh", vec![Fixed("a"), High(d8())], 2),
+            _ => ("ld", vec![Fixed("hl"), SpOffset(d8() as i8)], 2),
+        },
+        (_, 1) if q == 0 => ("pop", vec![Fixed(RP2[p])], 1),
+        (_, 1) => match p {
+            0 => ("ret", vec![], 1),
+            1 => ("reti", vec![], 1),
+            2 => ("jp", vec![Fixed("hl")], 1),
+            _ => ("ld", vec![Fixed("sp"), Fixed("hl")], 1),
+        },
+        (_, 2) => match y {
+            0..=3 => ("jp", vec![Fixed(CC[y]), Target(d16())], 3),
+            4 => ("ldh", vec![Fixed("[c]"), Fixed("a")], 1),
+            5 => ("ld", vec![Mem(d16()), Fixed("a")], 3),
+            6 => ("ldh", vec![Fixed("a"), Fixed("[c]")], 1),
+            _ => ("ld", vec![Fixed("a"), Mem(d16())], 3),
+        },
+        (_, 3) => match y {
+            0 => ("jp", vec![Target(d16())], 3),
+            1 => return decode_cb(d8(), address),
+            6 => ("di", vec![], 1),
+            7 => ("ei", vec![], 1),
+            _ => ("db", vec![Imm8(op)], 1),
+        },
+        (_, 4) if y < 4 => ("call", vec![Fixed(CC[y]), Target(d16())], 3),
+        (_, 5) if q == 0 => ("push", vec![Fixed(RP2[p])], 1),
+        (_, 5) if p == 0 => ("call", vec![Target(d16())], 3),
+        (_, 6) => alu(y, Imm8(d8()), 2),
+        (_, 7) => ("rst", vec![Vector((y * 8) as u8)], 1),
+        _ => ("db", vec![Imm8(op)], 1),
+    };
+
+    return Instruction {
+        address,
+        length,
+        mnemonic,
+        operands,
+    };
+}
+
+fn alu(y: usize, operand: Operand, length: usize) -> (&'static str, Vec<Operand>, usize) {
+    let (mnemonic, with_a) = ALU[y];
+    if with_a {
+        return (mnemonic, vec![Operand::Fixed("a"), operand], length);
+    }
+    return (mnemonic, vec![operand], length);
+}
+
+fn decode_cb(op: u8, address: u16) -> Instruction {
+    let (x, y, z) = ((op >> 6) as usize, (op >> 3) & 7, (op & 7) as usize);
+    let r = Operand::Fixed(R[z]);
+    let (mnemonic, operands) = match x {
+        0 => (ROT[y as usize], vec![r]),
+        1 => ("bit", vec![Operand::Bit(y), r]),
+        2 => ("res", vec![Operand::Bit(y), r]),
+        _ => ("set", vec![Operand::Bit(y), r]),
     };
-    let stdout = String::from_utf8(output.stdout).unwrap();
-    if stdout.len() > 0 {
-        println!("{}", stdout);
+    return Instruction {
+        address,
+        length: 2,
+        mnemonic,
+        operands,
+    };
+}
+
+pub fn bank_file_name(bank: usize) -> String {
+    return format!("bank_{:03x}.asm", bank);
+}
+
+/// `bank_file_name`のファイル名からバンクを得る
+pub fn bank_of_file(file: &str) -> Option<usize> {
+    let bank = file.strip_prefix("bank_")?.strip_suffix(".asm")?;
+    return usize::from_str_radix(bank, 16).ok();
+}
+
+fn format_label(prefix: &str, target: BankedAddress) -> String {
+    return format!("{}_{:03x}_{:04x}", prefix, target.bank, target.address);
+}
+
+/// 逆アセンブル結果のラベル表
+struct Labels {
+    labels: HashMap<BankedAddress, String>,
+}
+
+impl Labels {
+    fn new() -> Self {
+        let mut labels = HashMap::new();
+        for (address, name) in DEFAULT_SYMBOLS.iter() {
+            labels.insert(
+                BankedAddress::from_offset(*address as usize),
+                name.to_string(),
+            );
+        }
+        return Self { labels };
+    }
+
+    fn add(&mut self, traced: &Traced) {
+        let prefix = match traced.inst.mnemonic {
+            "call" => "Call",
+            "jp" => "Jump",
+            "jr" => "jr",
+            _ => return,
+        };
+        for target in &traced.targets {
+            // callのラベルを優先する
+            let replace = match self.labels.get(target) {
+                None => true,
+                Some(old) => {
+                    prefix == "Call" && (old.starts_with("Jump_") || old.starts_with("jr_"))
+                }
+            };
+            if replace {
+                self.labels.insert(*target, format_label(prefix, *target));
+            }
+        }
     }
-    let stderr = String::from_utf8(output.stderr).unwrap();
-    if stderr.len() > 0 {
-        println!("{}", stderr);
+
+    fn get(&self, address: &BankedAddress) -> Option<&String> {
+        return self.labels.get(address);
+    }
+}
+
+fn format_operand(operand: &Operand, target: Option<BankedAddress>, labels: &Labels) -> String {
+    match operand {
+        Operand::Fixed(s) => s.to_string(),
+        Operand::Imm8(v) => format!("${:02x}", v),
+        Operand::Imm16(v) => format!("${:04x}", v),
+        Operand::SpOffset(v) if *v < 0 => format!("sp-${:02x}", -(*v as i16)),
+        Operand::SpOffset(v) => format!("sp+${:02x}", v),
+        Operand::Mem(v) => match hardware_label(*v) {
+            Some(name) => format!("[{}]", name),
+            None => format!("[${:04x}]", v),
+        },
+        Operand::High(v) => match hardware_label(0xFF00 | *v as u16) {
+            Some(name) => format!("[{}]", name),
+            None => format!("[${:02x}]", v),
+        },
+        Operand::Target(v) => match target.as_ref().and_then(|t| labels.get(t)) {
+            Some(label) => label.clone(),
+            None => format!("${:04x}", v),
+        },
+        Operand::Vector(v) => format!("${:02x}", v),
+        Operand::Bit(v) => format!("{}", v),
+    }
+}
+
+fn hardware_label(address: u16) -> Option<&'static str> {
+    return HARDWARE_LABELS
+        .iter()
+        .find(|(a, _)| *a == address)
+        .map(|(_, name)| *name);
+}
+
+/// 命令のオペランドをrgbasmの表記に整形する
+fn format_operands(
+    inst: &Instruction,
+    target: Option<BankedAddress>,
+    labels: &Labels,
+) -> Vec<String> {
+    return inst
+        .operands
+        .iter()
+        .map(|o| format_operand(o, target, labels))
+        .collect();
+}
+
+/// 命令をrgbasmの表記に整形する
+fn format_instruction(inst: &Instruction, operands: &[String]) -> String {
+    if operands.is_empty() {
+        return format!("    {}", inst.mnemonic);
+    }
+    return format!("    {} {}", inst.mnemonic, operands.join(", "));
+}
+
+/// 割り込みベクタ(RST $00-$38、割り込み$40-$60)
+const VECTORS: [u16; 13] = [
+    0x00, 0x08, 0x10, 0x18, 0x20, 0x28, 0x30, 0x38, 0x40, 0x48, 0x50, 0x58, 0x60,
+];
+const ENTRY_POINT: u16 = 0x0100;
+
+/// 逆アセンブル結果
+pub struct Disassembly {
+    /// バンクごとのノード(`bank_000.asm`, `bank_001.asm`, ...)
+    pub files: HashMap<String, Nodes>,
+    /// コード探索の起点になったラベル
+    pub entries: Vec<String>,
+    /// バンクごとの逆アセンブルしたソースの行
+    pub sources: HashMap<String, Vec<String>>,
+    /// 逆アセンブル中に見つかった問題(行は`sources`の行)
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// 未使用のベクタは$00か$FFで埋められている
+fn is_filler(rom: &[u8], address: u16) -> bool {
+    let start = address as usize;
+    let end = (start + 8).min(rom.len());
+    if start >= end {
+        return true;
+    }
+    let head = rom[start];
+    return (head == 0x00 || head == 0xFF) && rom[start..end].iter().all(|b| *b == head);
+}
+
+/// 辿った命令と、その飛び先
+struct Traced {
+    inst: Instruction,
+    /// バンク0の命令は選択中のバンクごとに飛び先が変わるので、辿った順に全て残す
+    targets: Vec<BankedAddress>,
+}
+
+/// バンク切り替えを追うためにトレース中に分かっているレジスタの値
+#[derive(Clone, Copy, Default)]
+struct State {
+    a: Option<u8>,
+    hl: Option<u16>,
+    /// 選択中のROMXバンク
+    romx: Option<usize>,
+}
+
+impl State {
+    /// 命令を実行した後の状態
+    fn step(&mut self, inst: &Instruction, mbc: Mbc) {
+        use Operand::*;
+        let ops = &inst.operands[..];
+        match (inst.mnemonic, ops) {
+            ("ld", [Fixed("a"), Imm8(v)]) => self.a = Some(*v),
+            ("xor", [Fixed("a")]) => self.a = Some(0),
+            ("ld", [Fixed("hl"), Imm16(v)]) => self.hl = Some(*v),
+            ("ld", [Mem(address), Fixed("a")]) => self.write(*address, self.a, mbc),
+            ("ld", [Fixed("[hl]"), Fixed("a")]) => {
+                if let Some(address) = self.hl {
+                    self.write(address, self.a, mbc);
+                }
+            }
+            ("ld", [Fixed("[hl]"), Imm8(v)]) => {
+                if let Some(address) = self.hl {
+                    self.write(address, Some(*v), mbc);
+                }
+            }
+            ("call", _) | ("rst", _) => {
+                // 呼び出し先でレジスタが変わりうる
+                self.a = None;
+                self.hl = None;
+            }
+            ("pop", [Fixed("af")]) => self.a = None,
+            ("pop", [Fixed("hl")]) => self.hl = None,
+            (_, [Fixed("a"), ..]) | (_, [Bit(_), Fixed("a")]) => self.a = None,
+            ("add", [Fixed("hl"), _]) | ("inc", [Fixed("hl")]) | ("dec", [Fixed("hl")]) => {
+                self.hl = None
+            }
+            (_, [Fixed("h"), ..])
+            | (_, [Fixed("l"), ..])
+            | (_, [Fixed("[hl+]"), ..])
+            | (_, [Fixed("[hl-]"), ..])
+            | (_, [_, Fixed("[hl+]")])
+            | (_, [_, Fixed("[hl-]")]) => self.hl = None,
+            ("ld", [Fixed("hl"), _]) => self.hl = None,
+            ("sub", _) | ("and", _) | ("xor", _) | ("or", _) => self.a = None,
+            ("rlca", _) | ("rrca", _) | ("rla", _) | ("rra", _) | ("daa", _) | ("cpl", _) => {
+                self.a = None
+            }
+            _ => {}
+        }
+    }
+
+    fn write(&mut self, address: u16, value: Option<u8>, mbc: Mbc) {
+        match value {
+            Some(value) => {
+                if let Some(bank) = mbc.select_bank(address, value, self.romx) {
+                    self.romx = Some(bank);
+                }
+            }
+            None => {
+                // 値が分からないバンク切り替えの後は選択中のバンクも分からない
+                if mbc.select_bank(address, 0, self.romx).is_some() {
+                    self.romx = None;
+                }
+            }
+        }
+    }
+}
+
+/// エントリポイントから再帰下降でコードを辿り、命令の開始オフセットと命令の対応表を返す
+///
+/// $2000-$3FFFなどへの書き込みからROMXのバンクを追い、ROMXへの飛び先をそのバンクに解決する
+fn trace(rom: &[u8], entries: &[usize], mbc: Mbc) -> BTreeMap<usize, Traced> {
+    let num_of_banks = rom.len().div_ceil(BANK_SIZE);
+    let mut code: BTreeMap<usize, Traced> = BTreeMap::new();
+    // バンク0のコードは選択中のバンクごとに飛び先が変わるので状態ごとに辿る
+    let mut visited: HashSet<(usize, Option<usize>)> = HashSet::new();
+    let mut remaining: Vec<(usize, State)> =
+        entries.iter().map(|e| (*e, State::default())).collect();
+
+    while let Some((mut offset, mut state)) = remaining.pop() {
+        loop {
+            let here = BankedAddress::from_offset(offset);
+            let key = (offset, if here.bank == 0 { state.romx } else { None });
+            if offset >= rom.len() || visited.contains(&key) || HEADER_DATA.contains(&offset) {
+                break;
+            }
+            let inst = decode(rom, offset, here.address);
+            let bank_end = (here.bank + 1) * BANK_SIZE;
+            if inst.mnemonic == "db" || offset + inst.length > bank_end.min(rom.len()) {
+                // 未定義命令やバンク境界をまたぐ命令に当たったらそこで打ち切る
+                break;
+            }
+            visited.insert(key);
+
+            let target = inst
+                .target()
+                .and_then(|t| BankedAddress::resolve(here.bank, t, state.romx))
+                .filter(|t| t.bank < num_of_banks && t.offset() < rom.len());
+            state.step(&inst, mbc);
+            if let Some(target) = target {
+                remaining.push((target.offset(), state));
+            }
+            let ends_flow = match inst.mnemonic {
+                "jp" | "jr" => inst.operands.len() == 1,
+                "ret" => inst.operands.is_empty(),
+                "reti" => true,
+                _ => false,
+            };
+            let next = offset + inst.length;
+            let traced = code.entry(offset).or_insert(Traced {
+                inst,
+                targets: vec![],
+            });
+            if let Some(target) = target {
+                if !traced.targets.contains(&target) {
+                    traced.targets.push(target);
+                }
+            }
+            if ends_flow {
+                break;
+            }
+            offset = next;
+        }
+    }
+    return code;
+}
+
+/// ROMを逆アセンブルして、バンクごとのノードを返す
+///
+/// $0100と使用中の割り込みベクタからjp/jr/call/rstの飛び先を辿り、
+/// 辿れなかった領域はデータ(`db`)として出力する
+pub fn disassemble(rom: &[u8]) -> Result<Disassembly> {
+    if rom.len() <= ENTRY_POINT as usize {
+        return Err(Error::Disassemble(format!(
+            "the ROM is too small ({} bytes) to have the entry point ${:04X}",
+            rom.len(),
+            ENTRY_POINT
+        )));
+    }
+    let mbc = Header::parse(rom).map(|h| h.mbc).unwrap_or(Mbc::None);
+    let mut entries = vec![ENTRY_POINT];
+    entries.extend(VECTORS.iter().filter(|v| !is_filler(rom, **v)));
+    let entry_offsets: Vec<usize> = entries
+        .iter()
+        .filter(|a| (**a as usize) < rom.len())
+        .map(|a| *a as usize)
+        .collect();
+    let code = trace(rom, &entry_offsets, mbc);
+
+    let mut labels = Labels::new();
+    for traced in code.values() {
+        labels.add(traced);
+    }
+
+    let num_of_banks = rom.len().div_ceil(BANK_SIZE);
+    let mut files = HashMap::new();
+    let mut sources = HashMap::new();
+    let mut diagnostics = vec![];
+    for bank in 0..num_of_banks {
+        let mut p = Parser::with_text(
+            vec![],
+            Default::default(),
+            Default::default(),
+            HashMap::new(),
+        );
+        let mut source = vec![];
+        let mut data: Vec<String> = vec![];
+        let end = ((bank + 1) * BANK_SIZE).min(rom.len());
+        let mut offset = bank * BANK_SIZE;
+        while offset < end {
+            let traced = code.get(&offset);
+            let label = labels.get(&BankedAddress::from_offset(offset));
+            if label.is_some() || traced.is_some() || data.len() == 8 {
+                flush_data(&mut p, &mut source, &mut data);
+            }
+            if let Some(label) = label {
+                p.push_text("".to_string());
+                p.push_label(&(label.clone() + ":"));
+                source.push("".to_string());
+                source.push(label.clone() + ":");
+            }
+            match traced {
+                Some(Traced { inst, targets }) => {
+                    let operands = format_operands(inst, targets.first().copied(), &labels);
+                    let mut text = format_instruction(inst, &operands);
+                    // 命令の途中を指すラベル(別の経路で命令の途中から辿ったコードなど)
+                    let inside = (1..inst.length).find_map(|k| {
+                        let label = labels.get(&BankedAddress::from_offset(offset + k))?;
+                        Some((k, label))
+                    });
+                    if let Some((k, label)) = inside {
+                        let message = format!(
+                            "label {} points into the middle of `{}`",
+                            label,
+                            text.trim()
+                        );
+                        let diagnostic = Diagnostic::warning(&message)
+                            .at(&bank_file_name(bank), Some(source.len() + 1));
+                        diagnostics.push(diagnostic);
+                        if code.contains_key(&(offset + k)) {
+                            // ラベルまでをデータにして、ラベルから命令を続ける
+                            data.extend(
+                                rom[offset..offset + k]
+                                    .iter()
+                                    .map(|b| format!("${:02x}", b)),
+                            );
+                            offset += k;
+                            continue;
+                        }
+                    }
+                    // 別のバンク選択状態で辿った飛び先はコメントに残し、callや分岐の行き先にもする
+                    let alternatives: Vec<String> = targets
+                        .iter()
+                        .skip(1)
+                        .map(|t| format_operand(&Operand::Target(t.address), Some(*t), &labels))
+                        .collect();
+                    if !alternatives.is_empty() {
+                        text += &format!(" ; or {}", alternatives.join(", "));
+                    }
+                    let operand1 = operands.first().cloned().unwrap_or_default();
+                    let operand2 = operands.get(1).cloned().unwrap_or_default();
+                    source.push(text.clone());
+                    p.push_instruction(text, inst.mnemonic, &operand1, &operand2, alternatives);
+                    offset += inst.length;
+                }
+                None => {
+                    data.push(format!("${:02x}", rom[offset]));
+                    offset += 1;
+                }
+            }
+        }
+        flush_data(&mut p, &mut source, &mut data);
+        files.insert(bank_file_name(bank), p.into_nodes());
+        sources.insert(bank_file_name(bank), source);
+    }
+
+    let entries = entries
+        .iter()
+        .filter_map(|a| {
+            labels
+                .get(&BankedAddress::from_offset(*a as usize))
+                .cloned()
+        })
+        .collect();
+    return Ok(Disassembly {
+        files,
+        entries,
+        sources,
+        diagnostics,
+    });
+}
+
+fn flush_data(p: &mut Parser, source: &mut Vec<String>, data: &mut Vec<String>) {
+    if !data.is_empty() {
+        let text = format!("    db {}", data.join(", "));
+        source.push(text.clone());
+        p.push_text(text);
+        data.clear();
+    }
+}
+
+#[test]
+fn test_decode() {
+    let rom = [
+        0x00, 0xc3, 0x50, 0x01, 0x20, 0xfe, 0xcb, 0x7c, 0xe0, 0x44, 0xf8, 0xfe, 0xd3, 0xc4, 0x00,
+        0x40, 0xe2,
+    ];
+    let labels = Labels {
+        labels: HashMap::new(),
+    };
+    let mut offset = 0;
+    let mut texts = vec![];
+    while offset < rom.len() {
+        let inst = decode(&rom, offset, offset as u16);
+        let operands = format_operands(&inst, None, &labels);
+        texts.push(format_instruction(&inst, &operands).trim().to_string());
+        offset += inst.length;
+    }
+    assert_eq!(
+        texts,
+        vec![
+            "nop",
+            "jp $0150",
+            "jr nz, $0004",
+            "bit 7, h",
+            "ldh [rLY], a",
+            "ld hl, sp-$02",
+            "db $d3",
+            "call nz, $4000",
+            "ldh [c], a",
+        ]
+    );
+
+    let jr = decode(&rom, 4, 4);
+    assert_eq!(jr.target(), Some(4));
+}
+
+#[test]
+fn test_disassemble() {
+    let mut rom = vec![0u8; 0x8000];
+    // $0100: nop; jp $0150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+    // $0150: call $0160; jr $0150 / $0155: data
+    rom[0x150..0x158].copy_from_slice(&[0xcd, 0x60, 0x01, 0x18, 0xfb, 0xff, 0xff, 0xff]);
+    // $0160: ret
+    rom[0x160] = 0xc9;
+    // VBlank: reti
+    rom[0x40] = 0xd9;
+
+    let d = disassemble(&rom).unwrap();
+    assert_eq!(d.entries, vec!["Boot", "VBlankInterrupt"]);
+    let nodes = &d.files["bank_000.asm"];
+    assert!(nodes.contains_key("Jump_000_0150"));
+    assert!(nodes.contains_key("Call_000_0160"));
+    assert!(nodes["Call_000_0160"].text.contains("ret"));
+    // 辿れない$0155以降はデータ
+    assert!(nodes.values().any(|n| n.text.contains("db $ff, $ff, $ff")));
+    let source = &d.sources["bank_000.asm"];
+    let call = source.iter().position(|l| l == "Call_000_0160:").unwrap();
+    assert!(source[call + 1].contains("ret"));
+    assert!(disassemble(&rom[..0x100]).is_err());
+}
+
+#[test]
+fn test_disassemble_overlap() {
+    let mut rom = vec![0u8; 0x8000];
+    // $0100: nop; jp $0150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+    // $0150: jr z, $0153; ld a, $c9; ret (ld a, $c9の2バイト目をretとして飛び込む)
+    rom[0x150..0x155].copy_from_slice(&[0x28, 0x01, 0x3e, 0xc9, 0xc9]);
+
+    let d = disassemble(&rom).unwrap();
+    let source = &d.sources["bank_000.asm"];
+    let label = source.iter().position(|l| l == "jr_000_0153:").unwrap();
+    assert_eq!(source[label - 2], "    db $3e");
+    assert_eq!(source[label + 1], "    ret");
+    assert!(d.files["bank_000.asm"].contains_key("jr_000_0153"));
+    assert_eq!(d.diagnostics.len(), 1);
+    assert_eq!(d.diagnostics[0].line, Some(label - 1));
+}
+
+#[test]
+fn test_disassemble_banked() {
+    let mut rom = vec![0u8; 0x10000];
+    // MBC1
+    rom[0x147] = 0x01;
+    // $0100: nop; jp $0150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+    // $0150: ld a, 3; ld [$2000], a; call $4000; jr $0150
+    rom[0x150..0x15a]
+        .copy_from_slice(&[0x3e, 0x03, 0xea, 0x00, 0x20, 0xcd, 0x00, 0x40, 0x18, 0xf6]);
+    // バンク3の$4000: ret
+    rom[3 * 0x4000] = 0xc9;
+
+    let d = disassemble(&rom).unwrap();
+    let node = &d.files["bank_000.asm"]["Jump_000_0150"];
+    assert_eq!(node.calls, vec!["Call_003_4000"]);
+    assert!(d.files["bank_003.asm"].contains_key("Call_003_4000"));
+    assert!(!d.files["bank_001.asm"].contains_key("Call_001_4000"));
+}
+
+#[test]
+fn test_disassemble_bank_zero() {
+    let mut rom = vec![0u8; 0x10000];
+    // MBC5
+    rom[0x147] = 0x19;
+    // $0100: nop; jp $0150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+    // $0150: ld a, 0; ld [$2000], a; call $4200; jr $0150
+    rom[0x150..0x15a]
+        .copy_from_slice(&[0x3e, 0x00, 0xea, 0x00, 0x20, 0xcd, 0x00, 0x42, 0x18, 0xf6]);
+    // バンク0を選んだので$4200は$0200を指す
+    rom[0x200] = 0xc9;
+
+    let d = disassemble(&rom).unwrap();
+    let node = &d.files["bank_000.asm"]["Jump_000_0150"];
+    assert_eq!(node.calls, vec!["Call_000_0200"]);
+    assert!(d.files["bank_000.asm"].contains_key("Call_000_0200"));
+}
+
+#[test]
+fn test_disassemble_bank_conflict() {
+    let mut rom = vec![0u8; 0x10000];
+    // MBC1
+    rom[0x147] = 0x01;
+    // $0100: nop; jp $0150
+    rom[0x100..0x104].copy_from_slice(&[0x00, 0xc3, 0x50, 0x01]);
+    // $0150: バンク3を選んでcall $0170、バンク2を選んでcall $0170; jr $0150
+    rom[0x150..0x162].copy_from_slice(&[
+        0x3e, 0x03, 0xea, 0x00, 0x20, 0xcd, 0x70, 0x01, 0x3e, 0x02, 0xea, 0x00, 0x20, 0xcd, 0x70,
+        0x01, 0x18, 0xee,
+    ]);
+    // $0170: call $4000; jp $4000
+    rom[0x170..0x176].copy_from_slice(&[0xcd, 0x00, 0x40, 0xc3, 0x00, 0x40]);
+    rom[2 * 0x4000] = 0xc9;
+    rom[3 * 0x4000] = 0xc9;
+
+    let d = disassemble(&rom).unwrap();
+    // どちらのバンクの飛び先も残す
+    assert!(d.files["bank_002.asm"].contains_key("Call_002_4000"));
+    assert!(d.files["bank_003.asm"].contains_key("Call_003_4000"));
+    let source = &d.sources["bank_000.asm"];
+    let call = source.iter().position(|l| l == "Call_000_0170:").unwrap();
+    assert!(source[call + 1].contains("Call_002_4000"));
+    assert!(source[call + 1].contains("Call_003_4000"));
+
+    // callもjpもどちらのバンクにも辺を張る
+    use super::cfg::{Cfg, EdgeKind};
+    let expected = vec!["Call_002_4000", "Call_003_4000"];
+    let mut calls = d.files["bank_000.asm"]["Call_000_0170"].calls.clone();
+    calls.sort();
+    assert_eq!(calls, expected);
+    let cfg = Cfg::build(&d.files);
+    let block = cfg.block(cfg.find_in_file("bank_000.asm", "Call_000_0170").unwrap());
+    for kind in [EdgeKind::Call, EdgeKind::Taken] {
+        let mut targets: Vec<&str> = block
+            .successors
+            .iter()
+            .filter(|e| e.kind == kind)
+            .map(|e| &cfg.block(e.to.unwrap()).label[..])
+            .collect();
+        targets.sort();
+        assert_eq!(targets, expected);
     }
 }
@@ -0,0 +1,31 @@
+//! Graphviz dot形式の出力に使う関数
+
+/// dot形式の文字列リテラルにする
+pub fn quote(s: &str) -> String {
+    return format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+}
+
+/// recordのフィールドに入れられるようにエスケープする(各行は左揃えにする)
+pub fn record_field(s: &str) -> String {
+    let mut field = "".to_string();
+    for line in s.lines() {
+        for c in line.chars() {
+            match c {
+                '\\' | '"' | '{' | '}' | '|' | '<' | '>' => {
+                    field.push('\\');
+                    field.push(c);
+                }
+                '\t' => field.push_str("    "),
+                _ => field.push(c),
+            }
+        }
+        field += "\\l";
+    }
+    return field;
+}
+
+#[test]
+fn test_escape() {
+    assert_eq!(quote("say \"hi\""), "\"say \\\"hi\\\"\"");
+    assert_eq!(record_field("ld\ta,[hl]\n<x>"), "ld    a,[hl]\\l\\<x\\>\\l");
+}
@@ -0,0 +1,98 @@
+//! 解析や出力で起きるエラー
+//!
+//! 解析を続けられる問題は`Diagnostic`として集め、続けられないものをErrorとして返す
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    /// ファイルの読み書きに失敗した
+    Io { path: String, source: io::Error },
+    /// 扱えないパス(UTF-8でない、ディレクトリがないなど)
+    InvalidPath { path: String, reason: &'static str },
+    /// 入力を解析できない(jsonなど)
+    Parse(String),
+    /// コマンドラインで与えたシンボル(`-D`)を定義できない
+    InvalidDefinition(String),
+    /// INCLUDEしたファイルを読めない
+    MissingInclude { path: String, source: io::Error },
+    /// ROMを逆アセンブルできない
+    Disassemble(String),
+    /// 開始ラベルがどのファイルにも見つからない
+    UnresolvedEntry(String),
+    /// 出力できない形式(呼び出しグラフのhtmlなど)
+    UnsupportedFormat(String),
+    /// 解析結果をjsonに書き出せない
+    Serialize(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn io(path: &Path, source: io::Error) -> Self {
+        return Error::Io {
+            path: path.to_string_lossy().to_string(),
+            source,
+        };
+    }
+
+    pub fn invalid_path(path: &Path, reason: &'static str) -> Self {
+        return Error::InvalidPath {
+            path: path.to_string_lossy().to_string(),
+            reason,
+        };
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path, source),
+            Error::InvalidPath { path, reason } => write!(f, "{} {}", path, reason),
+            Error::Parse(message) => write!(f, "{}", message),
+            Error::InvalidDefinition(message) => write!(f, "{}", message),
+            Error::MissingInclude { path, source } => {
+                write!(f, "cannot open include file {}: {}", path, source)
+            }
+            Error::Disassemble(message) => write!(f, "cannot disassemble: {}", message),
+            Error::UnresolvedEntry(label) => write!(f, "label {} is not found", label),
+            Error::UnsupportedFormat(message) => write!(f, "{}", message),
+            Error::Serialize(message) => write!(f, "cannot serialize: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } | Error::MissingInclude { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// パスを文字列にする
+pub fn path_str(path: &Path) -> Result<String> {
+    match path.to_str() {
+        Some(s) => return Ok(s.to_string()),
+        None => return Err(Error::invalid_path(path, "is not valid UTF-8")),
+    }
+}
+
+#[test]
+fn test_error() {
+    let e = Error::io(
+        Path::new("main.asm"),
+        io::Error::new(io::ErrorKind::NotFound, "not found"),
+    );
+    assert_eq!(e.to_string(), "main.asm: not found");
+    assert!(std::error::Error::source(&e).is_some());
+    let e = Error::invalid_path(Path::new("out"), "is not a directory");
+    assert_eq!(e.to_string(), "out is not a directory");
+    assert_eq!(
+        Error::UnresolvedEntry("start".to_string()).to_string(),
+        "label start is not found"
+    );
+}
@@ -0,0 +1,680 @@
+//! RGBASMの式のパースと評価
+
+use std::collections::HashMap;
+use std::fmt;
+
+extern crate serde;
+use serde::{Deserialize, Serialize};
+
+use super::variable::token;
+
+/// 式の値(RGBASMの数値は32bit)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Str(String),
+}
+
+impl Value {
+    pub fn as_int(&self) -> Result<i64, String> {
+        match self {
+            Value::Int(v) => Ok(*v),
+            // 1文字の文字列は文字コードとして扱う
+            Value::Str(s) if s.chars().count() == 1 => Ok(s.chars().next().unwrap() as i64),
+            Value::Str(s) => Err(format!("\"{}\" is not a number", s)),
+        }
+    }
+
+    fn as_str(&self) -> Result<&str, String> {
+        match self {
+            Value::Str(s) => Ok(s),
+            Value::Int(v) => Err(format!("{} is not a string", v)),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Int(v) => write!(f, "{}", v),
+            Value::Str(s) => write!(f, "\"{}\"", s),
+        }
+    }
+}
+
+/// 式の中のシンボルやセクションの値を与える
+pub trait Env {
+    fn symbol(&self, name: &str) -> Option<Value>;
+
+    /// シンボルが定義されているか(DEF関数、値の分からないラベルも含む)
+    fn defined(&self, name: &str) -> bool {
+        return self.symbol(name).is_some();
+    }
+
+    /// シンボルのあるバンク(BANK関数)
+    fn bank(&self, _name: &str) -> Option<i64> {
+        None
+    }
+
+    /// セクションの先頭アドレス(STARTOF関数)
+    fn section_start(&self, _name: &str) -> Option<i64> {
+        None
+    }
+
+    /// セクションの大きさ(SIZEOF関数)
+    fn section_size(&self, _name: &str) -> Option<i64> {
+        None
+    }
+}
+
+impl Env for HashMap<String, Value> {
+    fn symbol(&self, name: &str) -> Option<Value> {
+        return self.get(name).cloned();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(i64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+}
+
+/// 長い順に並べた演算子
+const OPERATORS: [&str; 23] = [
+    ">>>", "**", "<<", ">>", "==", "!=", "<=", ">=", "&&", "||", "++", "+", "-", "*", "/", "%",
+    "&", "|", "^", "~", "!", "<", ">",
+];
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        // 数値リテラル($16進, %2進, &8進, 10進, 0x/0b/0o)
+        let radix = match c {
+            '$' => Some((16, 1)),
+            '%' if i + 1 < chars.len() && chars[i + 1].is_digit(2) && !prev_is_operand(&tokens) => {
+                Some((2, 1))
+            }
+            '&' if i + 1 < chars.len() && chars[i + 1].is_digit(8) && !prev_is_operand(&tokens) => {
+                Some((8, 1))
+            }
+            '0' if i + 1 < chars.len() && (chars[i + 1] == 'x' || chars[i + 1] == 'X') => {
+                Some((16, 2))
+            }
+            '0' if i + 1 < chars.len() && (chars[i + 1] == 'b' || chars[i + 1] == 'B') => {
+                Some((2, 2))
+            }
+            '0' if i + 1 < chars.len() && (chars[i + 1] == 'o' || chars[i + 1] == 'O') => {
+                Some((8, 2))
+            }
+            c if c.is_ascii_digit() => Some((10, 0)),
+            _ => None,
+        };
+        if let Some((radix, prefix)) = radix {
+            let start = i + prefix;
+            let mut end = start;
+            while end < chars.len() && (chars[end].is_digit(radix) || chars[end] == '_') {
+                end += 1;
+            }
+            let digits: String = chars[start..end].iter().filter(|&&c| c != '_').collect();
+            let value = i64::from_str_radix(&digits, radix).map_err(|_| {
+                format!(
+                    "invalid number: {}",
+                    chars[i..end].iter().collect::<String>()
+                )
+            })?;
+            tokens.push(Token::Int(value));
+            i = end;
+            continue;
+        }
+        // `01230123 のような2bppのグラフィック定数(下位バイトが下位ビットの面)
+        if c == '`' {
+            let mut end = i + 1;
+            let (mut low, mut high) = (0, 0);
+            while end < chars.len() && ('0'..='3').contains(&chars[end]) {
+                let pixel = chars[end] as i64 - '0' as i64;
+                low = low << 1 | pixel & 1;
+                high = high << 1 | pixel >> 1;
+                end += 1;
+            }
+            if end == i + 1 {
+                return Err("invalid graphics constant".to_string());
+            }
+            tokens.push(Token::Int(high << 8 | low));
+            i = end;
+            continue;
+        }
+        if c == '"' {
+            let mut end = i + 1;
+            let mut value = "".to_string();
+            while end < chars.len() && chars[end] != '"' {
+                if chars[end] == '\\' && end + 1 < chars.len() {
+                    end += 1;
+                    value.push(match chars[end] {
+                        'n' => '\n',
+                        't' => '\t',
+                        c => c,
+                    });
+                } else {
+                    value.push(chars[end]);
+                }
+                end += 1;
+            }
+            if end >= chars.len() {
+                return Err("unterminated string".to_string());
+            }
+            tokens.push(Token::Str(value));
+            i = end + 1;
+            continue;
+        }
+        if c.is_alphabetic() || c == '_' || c == '.' || c == '@' || c == '#' {
+            let mut end = i + 1;
+            while end < chars.len() && (chars[end].is_alphanumeric() || "_.@#".contains(chars[end]))
+            {
+                end += 1;
+            }
+            tokens.push(Token::Ident(chars[i..end].iter().collect()));
+            i = end;
+            continue;
+        }
+        match c {
+            '(' => tokens.push(Token::LParen),
+            ')' => tokens.push(Token::RParen),
+            ',' => tokens.push(Token::Comma),
+            _ => {
+                let rest: String = chars[i..chars.len().min(i + 3)].iter().collect();
+                match OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                    Some(op) => {
+                        tokens.push(Token::Op(op));
+                        i += op.len();
+                        continue;
+                    }
+                    None => return Err(format!("unexpected character: {}", c)),
+                }
+            }
+        }
+        i += 1;
+    }
+    return Ok(tokens);
+}
+
+/// 直前のトークンが値で終わっているか(`%`や`&`が演算子か数値の接頭辞かの判定に使う)
+fn prev_is_operand(tokens: &[Token]) -> bool {
+    return matches!(
+        tokens.last(),
+        Some(Token::Int(_)) | Some(Token::Str(_)) | Some(Token::Ident(_)) | Some(Token::RParen)
+    );
+}
+
+/// 式の構文木
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Int(i64),
+    Str(String),
+    Symbol(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+impl Expr {
+    /// シンボルを参照しているか
+    pub fn has_symbol(&self) -> bool {
+        match self {
+            Expr::Int(_) | Expr::Str(_) => false,
+            Expr::Symbol(_) => true,
+            Expr::Unary(_, e) => e.has_symbol(),
+            Expr::Binary(_, a, b) => a.has_symbol() || b.has_symbol(),
+            Expr::Call(_, args) => args.iter().any(|a| a.has_symbol()),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+/// 低い順に並べた二項演算子の優先順位(`&|^`は`+-`より高い)
+const BINARY_LEVELS: [&[&str]; 5] = [
+    &["&&", "||"],
+    &["==", "!=", "<=", ">=", "<", ">"],
+    &["+", "-", "++"],
+    &["&", "|", "^"],
+    &["*", "/", "%", "<<", ">>", ">>>"],
+];
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        return self.tokens.get(self.pos);
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        return token;
+    }
+
+    fn expect(&mut self, token: Token) -> Result<(), String> {
+        match self.next() {
+            Some(t) if t == token => Ok(()),
+            Some(t) => Err(format!("expected {:?}, found {:?}", token, t)),
+            None => Err(format!("expected {:?}", token)),
+        }
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == BINARY_LEVELS.len() {
+            return self.parse_unary();
+        }
+        let mut lhs = self.parse_binary(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Op(op)) if BINARY_LEVELS[level].contains(op) => *op,
+                _ => break,
+            };
+            self.pos += 1;
+            let rhs = self.parse_binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        return Ok(lhs);
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            // `!`は最も優先順位が低いので残りの式全体にかかる
+            Some(Token::Op("!")) => {
+                self.pos += 1;
+                return Ok(Expr::Unary("!", Box::new(self.parse_expr()?)));
+            }
+            Some(Token::Op(op)) if *op == "-" || *op == "+" || *op == "~" => {
+                let op = *op;
+                self.pos += 1;
+                return Ok(Expr::Unary(op, Box::new(self.parse_unary()?)));
+            }
+            _ => {}
+        }
+        let base = self.parse_primary()?;
+        if let Some(Token::Op("**")) = self.peek() {
+            self.pos += 1;
+            let exponent = self.parse_unary()?;
+            return Ok(Expr::Binary("**", Box::new(base), Box::new(exponent)));
+        }
+        return Ok(base);
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Int(v)) => Ok(Expr::Int(v)),
+            Some(Token::Str(s)) => Ok(Expr::Str(s)),
+            Some(Token::LParen) => {
+                let expr = self.parse_expr()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => {
+                if let Some(Token::LParen) = self.peek() {
+                    self.pos += 1;
+                    let mut args = vec![];
+                    if let Some(Token::RParen) = self.peek() {
+                        self.pos += 1;
+                    } else {
+                        loop {
+                            args.push(self.parse_expr()?);
+                            match self.next() {
+                                Some(Token::Comma) => continue,
+                                Some(Token::RParen) => break,
+                                _ => return Err(format!("unclosed call of {}", name)),
+                            }
+                        }
+                    }
+                    return Ok(Expr::Call(name.to_ascii_uppercase(), args));
+                }
+                Ok(Expr::Symbol(name))
+            }
+            Some(t) => Err(format!("unexpected {:?}", t)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, String> {
+        return self.parse_binary(0);
+    }
+}
+
+/// 式をパースする
+pub fn parse(s: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(s)?,
+        pos: 0,
+    };
+    let expr = parser.parse_expr()?;
+    if let Some(t) = parser.peek() {
+        return Err(format!("unexpected {:?}", t));
+    }
+    return Ok(expr);
+}
+
+/// 式をパースして評価する
+pub fn eval_str(s: &str, env: &dyn Env) -> Result<Value, String> {
+    return eval(&parse(s)?, env);
+}
+
+/// 32bitに切り詰める
+fn wrap(v: i64) -> i64 {
+    return v as i32 as i64;
+}
+
+pub fn eval(expr: &Expr, env: &dyn Env) -> Result<Value, String> {
+    match expr {
+        Expr::Int(v) => Ok(Value::Int(wrap(*v))),
+        Expr::Str(s) => Ok(Value::Str(s.clone())),
+        Expr::Symbol(name) => env
+            .symbol(name)
+            .ok_or_else(|| format!("{} is not defined", name)),
+        Expr::Unary(op, operand) => {
+            let v = eval(operand, env)?.as_int()?;
+            Ok(Value::Int(wrap(match *op {
+                "-" => -v,
+                "+" => v,
+                "~" => !v,
+                "!" => (v == 0) as i64,
+                _ => unreachable!(),
+            })))
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let lhs = eval(lhs, env)?;
+            let rhs = eval(rhs, env)?;
+            if let (Value::Str(a), Value::Str(b)) = (&lhs, &rhs) {
+                match *op {
+                    "++" => return Ok(Value::Str(a.clone() + b)),
+                    "==" => return Ok(Value::Int((a == b) as i64)),
+                    "!=" => return Ok(Value::Int((a != b) as i64)),
+                    _ => {}
+                }
+            }
+            let (a, b) = (lhs.as_int()?, rhs.as_int()?);
+            let v = match *op {
+                "+" => a + b,
+                "-" => a - b,
+                "*" => a * b,
+                "/" | "%" if b == 0 => return Err("division by zero".to_string()),
+                "/" => floor_div(a, b),
+                "%" => a - b * floor_div(a, b),
+                "**" if b < 0 => return Err("negative exponent".to_string()),
+                "**" => a.wrapping_pow(b as u32),
+                "<<" | ">>" | ">>>" if b < 0 => return Err("negative shift count".to_string()),
+                "<<" => a.checked_shl(b as u32).unwrap_or(0),
+                ">>" => a >> b.min(63),
+                ">>>" => ((a as u32).checked_shr(b as u32).unwrap_or(0)) as i64,
+                "&" => a & b,
+                "|" => a | b,
+                "^" => a ^ b,
+                "==" => (a == b) as i64,
+                "!=" => (a != b) as i64,
+                "<" => (a < b) as i64,
+                ">" => (a > b) as i64,
+                "<=" => (a <= b) as i64,
+                ">=" => (a >= b) as i64,
+                "&&" => (a != 0 && b != 0) as i64,
+                "||" => (a != 0 || b != 0) as i64,
+                _ => return Err(format!("invalid operator {}", op)),
+            };
+            Ok(Value::Int(wrap(v)))
+        }
+        Expr::Call(name, args) => call(name, args, env),
+    }
+}
+
+fn call(name: &str, args: &[Expr], env: &dyn Env) -> Result<Value, String> {
+    // シンボル名をそのまま受け取る関数
+    let symbol_arg = || match args {
+        [Expr::Symbol(s)] => Ok(s.as_str()),
+        [Expr::Str(s)] => Ok(s.as_str()),
+        _ => Err(format!("{} takes a symbol", name)),
+    };
+    match name {
+        "DEF" => return Ok(Value::Int(env.defined(symbol_arg()?) as i64)),
+        "ISCONST" => {
+            return Ok(Value::Int(
+                (args.len() == 1 && eval(&args[0], env).is_ok()) as i64,
+            ))
+        }
+        "BANK" => {
+            let symbol = symbol_arg()?;
+            return env
+                .bank(symbol)
+                .map(Value::Int)
+                .ok_or_else(|| format!("bank of {} is unknown", symbol));
+        }
+        "STARTOF" | "SIZEOF" => {
+            let section = symbol_arg()?;
+            let value = if name == "STARTOF" {
+                env.section_start(section)
+            } else {
+                env.section_size(section)
+            };
+            return value
+                .map(Value::Int)
+                .ok_or_else(|| format!("{}({}) is unknown", name, section));
+        }
+        _ => {}
+    }
+
+    let values = args
+        .iter()
+        .map(|a| eval(a, env))
+        .collect::<Result<Vec<Value>, String>>()?;
+    let int = |i: usize| -> Result<i64, String> {
+        values
+            .get(i)
+            .ok_or_else(|| format!("{} takes more arguments", name))?
+            .as_int()
+    };
+    let string = |i: usize| -> Result<&str, String> {
+        values
+            .get(i)
+            .ok_or_else(|| format!("{} takes more arguments", name))?
+            .as_str()
+    };
+    let value = match name {
+        "HIGH" => Value::Int(int(0)? >> 8 & 0xFF),
+        "LOW" => Value::Int(int(0)? & 0xFF),
+        "STRLEN" => Value::Int(string(0)?.chars().count() as i64),
+        "STRCAT" => Value::Str(
+            (0..values.len())
+                .map(string)
+                .collect::<Result<Vec<&str>, String>>()?
+                .concat(),
+        ),
+        "STRCMP" => Value::Int(match string(0)?.cmp(string(1)?) {
+            std::cmp::Ordering::Less => -1,
+            std::cmp::Ordering::Equal => 0,
+            std::cmp::Ordering::Greater => 1,
+        }),
+        // 位置は1始まり(見つからなければ0)
+        "STRIN" => Value::Int(char_position(string(0)?, string(0)?.find(string(1)?))),
+        "STRRIN" => Value::Int(char_position(string(0)?, string(0)?.rfind(string(1)?))),
+        "STRSUB" => {
+            let s: Vec<char> = string(0)?.chars().collect();
+            let start = (int(1)?.max(1) - 1) as usize;
+            let len = if values.len() > 2 {
+                int(2)?.max(0) as usize
+            } else {
+                s.len()
+            };
+            Value::Str(s.iter().skip(start).take(len).collect())
+        }
+        "STRUPR" => Value::Str(string(0)?.to_uppercase()),
+        "STRLWR" => Value::Str(string(0)?.to_lowercase()),
+        "STRRPL" => Value::Str(string(0)?.replace(string(1)?, string(2)?)),
+        _ => return Err(format!("unknown function {}", name)),
+    };
+    return Ok(value);
+}
+
+/// 負の無限大方向に丸める割り算
+fn floor_div(a: i64, b: i64) -> i64 {
+    let q = a / b;
+    if (a % b != 0) && ((a < 0) != (b < 0)) {
+        return q - 1;
+    }
+    return q;
+}
+
+fn char_position(s: &str, byte: Option<usize>) -> i64 {
+    match byte {
+        Some(byte) => s[..byte].chars().count() as i64 + 1,
+        None => 0,
+    }
+}
+
+/// `NAME EQU expr`などの定数を定義する行
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    /// EQU, SET, EQUS, =
+    pub directive: String,
+    pub expression: String,
+}
+
+/// 定数を定義する行なら名前と式に分ける(`DEF NAME EQU expr`の形も受け付ける)
+pub fn parse_definition(text: &str) -> Option<Definition> {
+    let mut s = strip_comment(text).trim();
+    if s.len() > token::DEF.len()
+        && s[..token::DEF.len()].eq_ignore_ascii_case(token::DEF)
+        && s[token::DEF.len()..].starts_with(char::is_whitespace)
+    {
+        s = s[token::DEF.len()..].trim_start();
+    }
+    let end = s
+        .find(|c: char| !(c.is_alphanumeric() || "_.@#".contains(c)))
+        .unwrap_or(s.len());
+    let name = &s[..end];
+    if !name.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '.') {
+        return None;
+    }
+    let rest = s[end..].trim_start_matches(':').trim_start();
+    let compound = ["<<=", ">>=", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^="]
+        .iter()
+        .find(|op| rest.starts_with(*op));
+    let (directive, expression) = if let Some(op) = compound {
+        // `NAME += expr`は`NAME = NAME + (expr)`とする
+        let expression = rest[op.len()..].trim();
+        if expression.is_empty() {
+            return None;
+        }
+        let expression = format!("{} {} ({})", name, &op[..op.len() - 1], expression);
+        ("=".to_string(), expression)
+    } else if rest.starts_with('=') && !rest.starts_with("==") {
+        ("=".to_string(), rest[1..].trim().to_string())
+    } else {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        let directive = rest[..end].to_ascii_uppercase();
+        if ![token::EQU, token::SET, token::EQUS].contains(&&directive[..]) {
+            return None;
+        }
+        (directive, rest[end..].trim().to_string())
+    };
+    if expression.is_empty() {
+        return None;
+    }
+    return Some(Definition {
+        name: name.to_string(),
+        directive,
+        expression,
+    });
+}
+
+/// 文字列の外にある`;`以降を取り除く
+pub fn strip_comment(text: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &text[..i],
+            _ => {}
+        }
+    }
+    return text;
+}
+
+/// 命令のオペランドを評価する(`[rLCDC]`のような間接参照も含む、レジスタなどはNone)
+pub fn operand_value(operand: &str, env: &dyn Env) -> Option<i64> {
+    let operand = operand.trim();
+    let operand = if operand.starts_with('[') && operand.ends_with(']') {
+        &operand[1..operand.len() - 1]
+    } else {
+        operand
+    };
+    return eval_str(operand, env).and_then(|v| v.as_int()).ok();
+}
+
+#[test]
+fn test_eval() {
+    let mut env: HashMap<String, Value> = HashMap::new();
+    env.insert("_SCRN0".to_string(), Value::Int(0x9800));
+    env.insert("SCRN_VX_B".to_string(), Value::Int(32));
+    env.insert("NAME".to_string(), Value::Str("hello".to_string()));
+    let int = |s: &str| eval_str(s, &env).and_then(|v| v.as_int());
+
+    assert_eq!(int("$2000-2"), Ok(0x1FFE));
+    assert_eq!(int("_SCRN0+SCRN_VX_B*2"), Ok(0x9840));
+    assert_eq!(int("%1010 | &17 | 0x100"), Ok(0x10F));
+    assert_eq!(int("10 % 4"), Ok(2));
+    assert_eq!(int("-7 / 2"), Ok(-4));
+    // &は+より優先順位が高い
+    assert_eq!(int("1 + 6 & 3"), Ok(3));
+    // !は比較より優先順位が低い
+    assert_eq!(int("!1 == 0"), Ok(1));
+    assert_eq!(int("-2 ** 2"), Ok(-4));
+    assert_eq!(int("$80000000 >>> 31"), Ok(1));
+    assert_eq!(int("HIGH($1234) + LOW($1234)"), Ok(0x46));
+    assert_eq!(int("DEF(NAME) && !DEF(UNDEFINED)"), Ok(1));
+    assert_eq!(int("STRLEN(NAME) + STRIN(NAME, \"l\")"), Ok(8));
+    assert_eq!(int("\"A\""), Ok(65));
+    assert_eq!(int("`01230123"), Ok(0x3355));
+    assert_eq!(
+        eval_str("STRUPR(STRSUB(NAME, 2, 3)) ++ STRCAT(\"!\", \"?\")", &env),
+        Ok(Value::Str("ELL!?".to_string()))
+    );
+    assert!(int("UNDEFINED + 1").is_err());
+    assert!(int("BANK(NAME)").is_err());
+    assert!(int("(1 + 2").is_err());
+    assert!(int("1 / 0").is_err());
+    assert!(int("1 >> -1").is_err());
+    assert!(int("1 << -1").is_err());
+
+    let definitions: Vec<Definition> = [
+        "SCRN_VX_B EQU 32 ; comment",
+        "_SCRN0: EQU $9800",
+        "DEF TILEMAP = _SCRN0+SCRN_VX_B*2",
+        "GREETING EQUS \"hi;\"",
+        "HARDWARE_INC  SET  1",
+    ]
+    .iter()
+    .map(|l| parse_definition(l).unwrap())
+    .collect();
+    assert_eq!(definitions[2].directive, "=");
+    assert_eq!(definitions[2].expression, "_SCRN0+SCRN_VX_B*2");
+    assert!(parse_definition("\tld a, [hl]").is_none());
+    assert!(parse_definition("start:").is_none());
+    assert_eq!(definitions[3].expression, "\"hi;\"");
+    assert_eq!(
+        parse_definition("x+=1").unwrap().expression,
+        "x + (1)".to_string()
+    );
+    assert_eq!(operand_value("[_SCRN0+SCRN_VX_B*2]", &env), Some(0x9840));
+    assert_eq!(operand_value("hl+", &env), None);
+}
@@ -0,0 +1,387 @@
+extern crate serde;
+use serde::{Deserialize, Serialize};
+
+use super::mbc::{Mbc, BANK_SIZE};
+
+/// $0104-$0133のNintendoロゴ
+const LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+const CARTRIDGE_TYPES: [(u8, &str); 28] = [
+    (0x00, "ROM ONLY"),
+    (0x01, "MBC1"),
+    (0x02, "MBC1+RAM"),
+    (0x03, "MBC1+RAM+BATTERY"),
+    (0x05, "MBC2"),
+    (0x06, "MBC2+BATTERY"),
+    (0x08, "ROM+RAM"),
+    (0x09, "ROM+RAM+BATTERY"),
+    (0x0B, "MMM01"),
+    (0x0C, "MMM01+RAM"),
+    (0x0D, "MMM01+RAM+BATTERY"),
+    (0x0F, "MBC3+TIMER+BATTERY"),
+    (0x10, "MBC3+TIMER+RAM+BATTERY"),
+    (0x11, "MBC3"),
+    (0x12, "MBC3+RAM"),
+    (0x13, "MBC3+RAM+BATTERY"),
+    (0x19, "MBC5"),
+    (0x1A, "MBC5+RAM"),
+    (0x1B, "MBC5+RAM+BATTERY"),
+    (0x1C, "MBC5+RUMBLE"),
+    (0x1D, "MBC5+RUMBLE+RAM"),
+    (0x1E, "MBC5+RUMBLE+RAM+BATTERY"),
+    (0x20, "MBC6"),
+    (0x22, "MBC7+SENSOR+RUMBLE+RAM+BATTERY"),
+    (0xFC, "POCKET CAMERA"),
+    (0xFD, "BANDAI TAMA5"),
+    (0xFE, "HuC3"),
+    (0xFF, "HuC1+RAM+BATTERY"),
+];
+
+/// 旧ライセンシーコード($014B)
+const OLD_LICENSEES: [(u8, &str); 40] = [
+    (0x00, "None"),
+    (0x01, "Nintendo"),
+    (0x08, "Capcom"),
+    (0x09, "Hot-B"),
+    (0x0A, "Jaleco"),
+    (0x0B, "Coconuts"),
+    (0x0C, "Elite Systems"),
+    (0x13, "Electronic Arts"),
+    (0x18, "Hudson Soft"),
+    (0x1F, "Virgin"),
+    (0x28, "Kotobuki Systems"),
+    (0x29, "Seta"),
+    (0x30, "Infogrames"),
+    (0x31, "Nintendo"),
+    (0x32, "Bandai"),
+    (0x34, "Konami"),
+    (0x38, "Capcom"),
+    (0x39, "Banpresto"),
+    (0x41, "Ubi Soft"),
+    (0x42, "Atlus"),
+    (0x49, "Irem"),
+    (0x51, "Acclaim"),
+    (0x52, "Activision"),
+    (0x56, "LJN"),
+    (0x60, "Titus"),
+    (0x67, "Ocean"),
+    (0x69, "Electronic Arts"),
+    (0x70, "Infogrames"),
+    (0x78, "THQ"),
+    (0x7F, "Kemco"),
+    (0xA4, "Konami"),
+    (0xAF, "Namco"),
+    (0xB4, "Enix"),
+    (0xB6, "HAL Laboratory"),
+    (0xB7, "SNK"),
+    (0xBB, "Sunsoft"),
+    (0xC0, "Taito"),
+    (0xC3, "Square"),
+    (0xE9, "Natsume"),
+    (0xFF, "LJN"),
+];
+
+/// 新ライセンシーコード($0144-$0145)
+const NEW_LICENSEES: [(&str, &str); 32] = [
+    ("00", "None"),
+    ("01", "Nintendo"),
+    ("08", "Capcom"),
+    ("13", "Electronic Arts"),
+    ("18", "Hudson Soft"),
+    ("20", "KSS"),
+    ("28", "Kemco"),
+    ("29", "Seta"),
+    ("30", "Viacom"),
+    ("31", "Nintendo"),
+    ("32", "Bandai"),
+    ("33", "Ocean/Acclaim"),
+    ("34", "Konami"),
+    ("37", "Taito"),
+    ("38", "Hudson Soft"),
+    ("39", "Banpresto"),
+    ("41", "Ubi Soft"),
+    ("42", "Atlus"),
+    ("49", "Irem"),
+    ("51", "Acclaim"),
+    ("52", "Activision"),
+    ("56", "LJN"),
+    ("60", "Titus"),
+    ("61", "Virgin"),
+    ("64", "LucasArts"),
+    ("67", "Ocean"),
+    ("69", "Electronic Arts"),
+    ("70", "Infogrames"),
+    ("78", "THQ"),
+    ("91", "Chunsoft"),
+    ("97", "Kaneko"),
+    ("A4", "Konami"),
+];
+
+/// CGB対応
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Cgb {
+    /// DMG専用
+    None,
+    /// CGB拡張あり(DMGでも動作する)
+    Enhanced,
+    /// CGB専用
+    Only,
+}
+
+/// カートリッジヘッダ($0100-$014F)の内容
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Header {
+    pub title: String,
+    pub cgb: Cgb,
+    pub sgb: bool,
+    pub cartridge_type: u8,
+    pub cartridge_name: String,
+    pub mbc: Mbc,
+    pub rom_size: u8,
+    /// ヘッダに書かれたROMのバンク数(不明なコードならNone)
+    pub rom_banks: Option<usize>,
+    pub ram_size: u8,
+    /// ヘッダに書かれたRAMのサイズ(バイト)
+    pub ram_bytes: Option<usize>,
+    pub licensee: String,
+    pub japanese: bool,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    /// 逆アセンブルしたROMのバンク数(ファイルサイズから求める)
+    pub analyzed_banks: usize,
+    /// 検証で見つかった問題
+    pub warnings: Vec<String>,
+}
+
+impl Header {
+    /// ROMのヘッダを読む(ヘッダ領域に満たないならNone)
+    pub fn parse(rom: &[u8]) -> Option<Self> {
+        if rom.len() < 0x150 {
+            return None;
+        }
+
+        let cgb = match rom[0x143] {
+            0xC0 => Cgb::Only,
+            0x80 => Cgb::Enhanced,
+            _ => Cgb::None,
+        };
+        // CGB対応のROMはタイトルが15バイト以下になる
+        let title_end = if cgb == Cgb::None { 0x144 } else { 0x143 };
+        let title: String = rom[0x134..title_end]
+            .iter()
+            .take_while(|b| **b != 0)
+            .map(|b| {
+                if b.is_ascii_graphic() || *b == b' ' {
+                    *b as char
+                } else {
+                    '?'
+                }
+            })
+            .collect();
+
+        let cartridge_type = rom[0x147];
+        let cartridge_name = CARTRIDGE_TYPES
+            .iter()
+            .find(|(t, _)| *t == cartridge_type)
+            .map(|(_, name)| name.to_string())
+            .unwrap_or_else(|| "UNKNOWN".to_string());
+
+        let rom_size = rom[0x148];
+        let rom_banks = match rom_size {
+            0x00..=0x08 => Some(2 << rom_size),
+            0x52 => Some(72),
+            0x53 => Some(80),
+            0x54 => Some(96),
+            _ => None,
+        };
+        let ram_size = rom[0x149];
+        let ram_bytes = match ram_size {
+            0x00 => Some(0),
+            0x01 => Some(0x800),
+            0x02 => Some(0x2000),
+            0x03 => Some(0x8000),
+            0x04 => Some(0x20000),
+            0x05 => Some(0x10000),
+            _ => None,
+        };
+
+        let old_licensee = rom[0x14B];
+        let licensee = if old_licensee == 0x33 {
+            let code: String = rom[0x144..0x146].iter().map(|b| *b as char).collect();
+            let name = NEW_LICENSEES
+                .iter()
+                .find(|(c, _)| *c == code)
+                .map(|(_, n)| *n);
+            format!("\"{}\" {}", code, name.unwrap_or("Unknown"))
+        } else {
+            let name = OLD_LICENSEES
+                .iter()
+                .find(|(c, _)| *c == old_licensee)
+                .map(|(_, n)| *n);
+            format!("${:02X} {}", old_licensee, name.unwrap_or("Unknown"))
+        };
+
+        let header_checksum = rom[0x14D];
+        let global_checksum = (rom[0x14E] as u16) << 8 | rom[0x14F] as u16;
+
+        let mut header = Self {
+            title,
+            cgb,
+            sgb: rom[0x146] == 0x03 && old_licensee == 0x33,
+            cartridge_type,
+            cartridge_name,
+            mbc: Mbc::from_cartridge_type(cartridge_type),
+            rom_size,
+            rom_banks,
+            ram_size,
+            ram_bytes,
+            licensee,
+            japanese: rom[0x14A] == 0x00,
+            version: rom[0x14C],
+            header_checksum,
+            global_checksum,
+            analyzed_banks: rom.len().div_ceil(BANK_SIZE),
+            warnings: vec![],
+        };
+        header.warnings = header.validate(rom);
+        return Some(header);
+    }
+
+    fn validate(&self, rom: &[u8]) -> Vec<String> {
+        let mut warnings = vec![];
+        if rom[0x104..0x134] != LOGO[..] {
+            warnings.push("Nintendo logo does not match".to_string());
+        }
+        let expected = header_checksum(rom);
+        if expected != self.header_checksum {
+            warnings.push(format!(
+                "header checksum is ${:02X}, expected ${:02X}",
+                self.header_checksum, expected
+            ));
+        }
+        let expected = global_checksum(rom);
+        if expected != self.global_checksum {
+            warnings.push(format!(
+                "global checksum is ${:04X}, expected ${:04X}",
+                self.global_checksum, expected
+            ));
+        }
+        if self.cartridge_name == "UNKNOWN" {
+            warnings.push(format!(
+                "unknown cartridge type ${:02X}",
+                self.cartridge_type
+            ));
+        }
+        match self.rom_banks {
+            Some(banks) if banks != self.analyzed_banks => warnings.push(format!(
+                "header declares {} ROM banks but the file has {}",
+                banks, self.analyzed_banks
+            )),
+            None => warnings.push(format!("unknown ROM size ${:02X}", self.rom_size)),
+            _ => {}
+        }
+        if self.ram_bytes.is_none() {
+            warnings.push(format!("unknown RAM size ${:02X}", self.ram_size));
+        }
+        return warnings;
+    }
+
+    /// flowchartの先頭に付けるコメント
+    pub fn summary(&self) -> Vec<String> {
+        let rom = match self.rom_banks {
+            Some(banks) => format!("{} KiB ({} banks)", banks * 16, banks),
+            None => "unknown".to_string(),
+        };
+        let ram = match self.ram_bytes {
+            Some(0) => "none".to_string(),
+            Some(bytes) => format!("{} KiB", bytes / 1024),
+            None => "unknown".to_string(),
+        };
+        let cgb = match self.cgb {
+            Cgb::None => "no",
+            Cgb::Enhanced => "enhanced",
+            Cgb::Only => "only",
+        };
+        let mut lines = vec![
+            format!("; title: {}", self.title),
+            format!(
+                "; cartridge: ${:02X} {} (MBC: {:?})",
+                self.cartridge_type, self.cartridge_name, self.mbc
+            ),
+            format!(
+                "; ROM: {}, RAM: {}, analyzed banks: {}",
+                rom, ram, self.analyzed_banks
+            ),
+            format!(
+                "; CGB: {}, SGB: {}, licensee: {}, destination: {}, version: {}",
+                cgb,
+                if self.sgb { "yes" } else { "no" },
+                self.licensee,
+                if self.japanese { "Japan" } else { "overseas" },
+                self.version
+            ),
+            format!(
+                "; header checksum: ${:02X}, global checksum: ${:04X}",
+                self.header_checksum, self.global_checksum
+            ),
+        ];
+        for warning in &self.warnings {
+            lines.push(format!("; warning: {}", warning));
+        }
+        return lines;
+    }
+}
+
+/// $0134-$014Cのヘッダチェックサム
+pub fn header_checksum(rom: &[u8]) -> u8 {
+    return rom[0x134..=0x14C]
+        .iter()
+        .fold(0u8, |x, b| x.wrapping_sub(*b).wrapping_sub(1));
+}
+
+/// チェックサム自身を除いたROM全体の和
+pub fn global_checksum(rom: &[u8]) -> u16 {
+    return rom
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != 0x14E && *i != 0x14F)
+        .fold(0u16, |sum, (_, b)| sum.wrapping_add(*b as u16));
+}
+
+#[test]
+fn test_parse_header() {
+    let mut rom = vec![0u8; 0x10000];
+    rom[0x104..0x134].copy_from_slice(&LOGO);
+    rom[0x134..0x139].copy_from_slice(b"HELLO");
+    rom[0x143] = 0x80;
+    rom[0x147] = 0x13;
+    rom[0x148] = 0x01;
+    rom[0x149] = 0x03;
+    rom[0x14B] = 0x33;
+    rom[0x144..0x146].copy_from_slice(b"01");
+    rom[0x14D] = header_checksum(&rom);
+    let sum = global_checksum(&rom);
+    rom[0x14E] = (sum >> 8) as u8;
+    rom[0x14F] = sum as u8;
+
+    let header = Header::parse(&rom).unwrap();
+    assert_eq!(header.title, "HELLO");
+    assert_eq!(header.cgb, Cgb::Enhanced);
+    assert_eq!(header.mbc, Mbc::Mbc3);
+    assert_eq!(header.cartridge_name, "MBC3+RAM+BATTERY");
+    assert_eq!(header.rom_banks, Some(4));
+    assert_eq!(header.ram_bytes, Some(0x8000));
+    assert_eq!(header.licensee, "\"01\" Nintendo");
+    assert!(header.warnings.is_empty());
+
+    rom[0x14D] ^= 0xFF;
+    rom[0x148] = 0x02;
+    let header = Header::parse(&rom).unwrap();
+    assert_eq!(header.warnings.len(), 3);
+    assert!(Header::parse(&rom[..0x100]).is_none());
+}
@@ -0,0 +1,190 @@
+//! オフラインで見られるHTMLビューアの出力
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+extern crate serde;
+use serde::Serialize;
+
+use super::cfg::{BlockId, Cfg, EdgeKind};
+use super::error::{Error, Result};
+use super::parser::Nodes;
+use super::symbol::SymbolTable;
+
+/// ビューアのテンプレート(`/*DATA*/`をグラフのjsonに置き換える)
+const TEMPLATE: &str = include_str!("viewer.html");
+
+#[derive(Serialize, Debug)]
+struct Viewer {
+    title: String,
+    /// ファイル先頭に出すコメント(ROMのヘッダなど)
+    summary: Vec<String>,
+    charts: Vec<Chart>,
+}
+
+/// 1つの開始地点(開始ラベルかcallの呼び出し先)から辿れるブロックの図
+#[derive(Serialize, Debug)]
+struct Chart {
+    label: String,
+    file: String,
+    blocks: Vec<Block>,
+}
+
+#[derive(Serialize, Debug)]
+struct Block {
+    label: String,
+    lines: Vec<String>,
+    conditional: bool,
+    edges: Vec<Edge>,
+    calls: Vec<Call>,
+}
+
+#[derive(Serialize, Debug)]
+struct Edge {
+    /// 飛び先のブロック(`Chart::blocks`の添字)
+    to: Option<usize>,
+    /// 飛び先が見つからない場合のラベル
+    label: String,
+    kind: EdgeKind,
+    condition: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct Call {
+    label: String,
+    /// 呼び出し先の図(`Viewer::charts`の添字)
+    chart: Option<usize>,
+}
+
+/// `starts`から辿れる図と、そこからcallで呼び出される図をまとめたHTMLを作る
+pub fn render(
+    title: &str,
+    summary: Vec<String>,
+    asm: &HashMap<String, Nodes>,
+    symbols: &SymbolTable,
+    cfg: &Cfg,
+    starts: &[BlockId],
+) -> Result<String> {
+    // 開始ブロックから図の添字への対応表
+    let mut charts: Vec<BlockId> = vec![];
+    let mut index: HashMap<BlockId, usize> = HashMap::new();
+    for &start in starts {
+        if let Entry::Vacant(entry) = index.entry(start) {
+            entry.insert(charts.len());
+            charts.push(start);
+        }
+    }
+
+    let mut viewer = Viewer {
+        title: title.to_string(),
+        summary,
+        charts: vec![],
+    };
+    let mut i = 0;
+    while i < charts.len() {
+        let start = charts[i];
+        let reachable = cfg.reachable(&[start]);
+        let position: HashMap<BlockId, usize> = reachable
+            .iter()
+            .enumerate()
+            .map(|(i, &id)| (id, i))
+            .collect();
+
+        let mut blocks = vec![];
+        for &id in &reachable {
+            let block = cfg.block(id);
+            let mut edges = vec![];
+            let mut calls = vec![];
+            for edge in &block.successors {
+                if edge.kind == EdgeKind::Call {
+                    // 呼び出し先は別の図にする
+                    let chart = edge.to.map(|to| match index.get(&to) {
+                        Some(&chart) => chart,
+                        None => {
+                            index.insert(to, charts.len());
+                            charts.push(to);
+                            charts.len() - 1
+                        }
+                    });
+                    calls.push(Call {
+                        label: edge.label.clone(),
+                        chart,
+                    });
+                    continue;
+                }
+                edges.push(Edge {
+                    to: edge.to.and_then(|to| position.get(&to).cloned()),
+                    label: edge.label.clone(),
+                    kind: edge.kind,
+                    condition: edge.condition.clone(),
+                });
+            }
+            blocks.push(Block {
+                label: block.label.clone(),
+                lines: cfg
+                    .body(asm, symbols, id)
+                    .iter()
+                    .map(|l| l.replace('\t', "    "))
+                    .collect(),
+                conditional: block.is_conditional(),
+                edges,
+                calls,
+            });
+        }
+
+        let block = cfg.block(start);
+        viewer.charts.push(Chart {
+            label: block.label.clone(),
+            file: block.file.clone(),
+            blocks,
+        });
+        i += 1;
+    }
+
+    // </script>でスクリプトが途切れないようにする
+    let json = serde_json::to_string(&viewer)
+        .map_err(|e| Error::Serialize(e.to_string()))?
+        .replace("</", "<\\/");
+    return Ok(TEMPLATE
+        .replace("/*TITLE*/", &escape(title))
+        .replace("/*DATA*/", &json));
+}
+
+/// HTMLのテキストとしてエスケープする
+fn escape(s: &str) -> String {
+    return s
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+}
+
+#[test]
+fn test_render() {
+    let text = "start:
+\tcall sub
+\tjr nz, start
+\tret
+sub:
+\tld a, \"</script>\"
+\tret
+";
+    let (asm, _, _) = super::parser::parse_snippet(text);
+    let cfg = Cfg::build(&asm);
+    let start = cfg.find(None, "start").unwrap();
+    let html = render(
+        "main<1>",
+        vec![],
+        &asm,
+        &SymbolTable::default(),
+        &cfg,
+        &[start],
+    )
+    .unwrap();
+
+    assert!(html.contains("<title>main&lt;1&gt; - flowchart.gb</title>"));
+    assert!(!html.contains("/*DATA*/"));
+    assert!(html.contains(r#""calls":[{"label":"sub","chart":1}]"#));
+    assert!(html.contains(r#""label":"sub","file":"main.asm""#));
+    assert!(html.contains(r#"ld a, \"<\/script>\""#));
+}
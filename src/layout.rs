@@ -0,0 +1,460 @@
+//! 有向グラフの階層型レイアウト(Sugiyama法)
+//!
+//! 閉路の除去、最長路による層の割り当て、ダミーノードの挿入、重心法による交差の削減、
+//! 座標の割り当ての順に行い、辺は直交する折れ線で引く
+
+pub type Point = (f64, f64);
+
+/// 層の間隔
+const LAYER_GAP: f64 = 48.0;
+/// 同じ層のノードの間隔
+const NODE_GAP: f64 = 32.0;
+/// 自己ループの辺がノードの右にはみ出す幅
+const LOOP_WIDTH: f64 = 16.0;
+const SWEEPS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Layout {
+    /// 入力のノードと同じ順の位置
+    pub nodes: Vec<Rect>,
+    /// 入力の辺と同じ順の折れ線(始点から終点へ)
+    pub edges: Vec<Vec<Point>>,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// レイアウト中の頂点(ダミーノードを含む)
+struct Vertex {
+    w: f64,
+    h: f64,
+    layer: usize,
+    x: f64,
+    preds: Vec<usize>,
+    succs: Vec<usize>,
+}
+
+/// `sizes`の大きさのノードを`edges`(始点, 終点)で繋いだグラフを配置する
+pub fn layout(sizes: &[(f64, f64)], edges: &[(usize, usize)]) -> Layout {
+    let n = sizes.len();
+
+    // 閉路を除くため、DFSで後退辺を見つけて向きを反転する
+    let reversed = back_edges(n, edges);
+    let dag: Vec<(usize, usize)> = edges
+        .iter()
+        .zip(&reversed)
+        .map(|(&(from, to), &r)| if r { (to, from) } else { (from, to) })
+        .collect();
+
+    // 最長路で層を割り当てる
+    let layers_of = assign_layers(n, &dag);
+
+    // 2層以上にまたがる辺にはダミーノードを挟む
+    let mut vertices: Vec<Vertex> = sizes
+        .iter()
+        .zip(&layers_of)
+        .map(|(&(w, h), &layer)| Vertex {
+            w,
+            h,
+            layer,
+            x: 0.0,
+            preds: vec![],
+            succs: vec![],
+        })
+        .collect();
+    let mut chains: Vec<Vec<usize>> = vec![];
+    for &(from, to) in &dag {
+        if from == to {
+            chains.push(vec![from]);
+            continue;
+        }
+        let mut chain = vec![from];
+        for layer in layers_of[from] + 1..layers_of[to] {
+            vertices.push(Vertex {
+                w: 0.0,
+                h: 0.0,
+                layer,
+                x: 0.0,
+                preds: vec![],
+                succs: vec![],
+            });
+            chain.push(vertices.len() - 1);
+        }
+        chain.push(to);
+        for pair in chain.windows(2) {
+            vertices[pair[0]].succs.push(pair[1]);
+            vertices[pair[1]].preds.push(pair[0]);
+        }
+        chains.push(chain);
+    }
+
+    let layers = order_layers(&vertices);
+    assign_x(&mut vertices, &layers);
+
+    // 層ごとの上端と高さ
+    let mut tops = vec![];
+    let mut heights = vec![];
+    let mut y = 0.0;
+    for layer in &layers {
+        let h = layer.iter().map(|&v| vertices[v].h).fold(0.0, f64::max);
+        tops.push(y);
+        heights.push(h);
+        y += h + LAYER_GAP;
+    }
+
+    // 最も左のノードがx=0になるように寄せる
+    let left = vertices
+        .iter()
+        .map(|v| v.x - v.w / 2.0)
+        .fold(f64::INFINITY, f64::min);
+    let left = if left.is_finite() { left } else { 0.0 };
+    for v in vertices.iter_mut() {
+        v.x -= left;
+    }
+
+    let nodes: Vec<Rect> = (0..n)
+        .map(|i| {
+            let v = &vertices[i];
+            Rect {
+                x: v.x - v.w / 2.0,
+                y: tops[v.layer],
+                w: v.w,
+                h: v.h,
+            }
+        })
+        .collect();
+
+    let routes = route_edges(&vertices, &chains, &nodes, &tops, &heights);
+    let edges: Vec<Vec<Point>> = routes
+        .into_iter()
+        .zip(&reversed)
+        .map(|(mut points, &r)| {
+            if r {
+                points.reverse();
+            }
+            points
+        })
+        .collect();
+
+    let mut width: f64 = 0.0;
+    let mut height: f64 = 0.0;
+    for rect in &nodes {
+        width = width.max(rect.x + rect.w);
+        height = height.max(rect.y + rect.h);
+    }
+    for &(x, y) in edges.iter().flatten() {
+        width = width.max(x);
+        height = height.max(y);
+    }
+    return Layout {
+        nodes,
+        edges,
+        width,
+        height,
+    };
+}
+
+/// DFSで見つかる後退辺(反転すると閉路がなくなる辺)
+fn back_edges(n: usize, edges: &[(usize, usize)]) -> Vec<bool> {
+    let mut succs: Vec<Vec<(usize, usize)>> = vec![vec![]; n];
+    let mut has_pred = vec![false; n];
+    for (i, &(from, to)) in edges.iter().enumerate() {
+        succs[from].push((to, i));
+        if from != to {
+            has_pred[to] = true;
+        }
+    }
+
+    // 0: 未訪問, 1: 探索中, 2: 探索済み
+    let mut state = vec![0u8; n];
+    let mut reversed = vec![false; edges.len()];
+    // 入ってくる辺のないノードから探索する
+    let roots = (0..n).filter(|&v| !has_pred[v]).chain(0..n);
+    for root in roots.collect::<Vec<_>>() {
+        if state[root] != 0 {
+            continue;
+        }
+        let mut stack = vec![(root, 0)];
+        state[root] = 1;
+        while let Some(&mut (v, ref mut i)) = stack.last_mut() {
+            if *i < succs[v].len() {
+                let (to, edge) = succs[v][*i];
+                *i += 1;
+                match state[to] {
+                    0 => {
+                        state[to] = 1;
+                        stack.push((to, 0));
+                    }
+                    1 if to != v => reversed[edge] = true,
+                    _ => {}
+                }
+            } else {
+                state[v] = 2;
+                stack.pop();
+            }
+        }
+    }
+    return reversed;
+}
+
+/// 入ってくる辺のないノードを層0とした最長路で層を決める
+fn assign_layers(n: usize, dag: &[(usize, usize)]) -> Vec<usize> {
+    let mut indegree = vec![0; n];
+    let mut succs: Vec<Vec<usize>> = vec![vec![]; n];
+    for &(from, to) in dag {
+        if from != to {
+            indegree[to] += 1;
+            succs[from].push(to);
+        }
+    }
+    let mut layers = vec![0; n];
+    let mut queue: Vec<usize> = (0..n).filter(|&v| indegree[v] == 0).collect();
+    let mut i = 0;
+    while i < queue.len() {
+        let v = queue[i];
+        i += 1;
+        for &to in &succs[v] {
+            layers[to] = layers[to].max(layers[v] + 1);
+            indegree[to] -= 1;
+            if indegree[to] == 0 {
+                queue.push(to);
+            }
+        }
+    }
+    return layers;
+}
+
+/// 重心法で層内の並び順を決める
+fn order_layers(vertices: &[Vertex]) -> Vec<Vec<usize>> {
+    let count = vertices.iter().map(|v| v.layer + 1).max().unwrap_or(0);
+    let mut layers: Vec<Vec<usize>> = vec![vec![]; count];
+    for (i, v) in vertices.iter().enumerate() {
+        layers[v.layer].push(i);
+    }
+    let mut position = vec![0.0; vertices.len()];
+    for layer in &layers {
+        for (i, &v) in layer.iter().enumerate() {
+            position[v] = i as f64;
+        }
+    }
+
+    for sweep in 0..SWEEPS {
+        let down = sweep % 2 == 0;
+        let range: Vec<usize> = if down {
+            (1..count).collect()
+        } else {
+            (0..count.saturating_sub(1)).rev().collect()
+        };
+        for l in range {
+            let mut keyed: Vec<(f64, usize)> = layers[l]
+                .iter()
+                .map(|&v| {
+                    let neighbors = if down {
+                        &vertices[v].preds
+                    } else {
+                        &vertices[v].succs
+                    };
+                    let key = if neighbors.is_empty() {
+                        position[v]
+                    } else {
+                        neighbors.iter().map(|&u| position[u]).sum::<f64>() / neighbors.len() as f64
+                    };
+                    (key, v)
+                })
+                .collect();
+            // 同じ重心なら元の並びを保つ
+            keyed.sort_by(|a, b| {
+                a.0.partial_cmp(&b.0)
+                    .unwrap()
+                    .then(position[a.1].partial_cmp(&position[b.1]).unwrap())
+            });
+            layers[l] = keyed.into_iter().map(|(_, v)| v).collect();
+            for (i, &v) in layers[l].iter().enumerate() {
+                position[v] = i as f64;
+            }
+        }
+    }
+    return layers;
+}
+
+/// 隣接する層のノードの中心に寄せつつ、重ならないようにx座標(中心)を決める
+fn assign_x(vertices: &mut [Vertex], layers: &[Vec<usize>]) {
+    for layer in layers {
+        let mut x = 0.0;
+        for &v in layer {
+            vertices[v].x = x + vertices[v].w / 2.0;
+            x += vertices[v].w + NODE_GAP;
+        }
+    }
+
+    for sweep in 0..SWEEPS {
+        let down = sweep % 2 == 0;
+        let order: Vec<&Vec<usize>> = if down {
+            layers.iter().collect()
+        } else {
+            layers.iter().rev().collect()
+        };
+        for layer in order {
+            let desired: Vec<f64> = layer
+                .iter()
+                .map(|&v| {
+                    let neighbors = if down {
+                        &vertices[v].preds
+                    } else {
+                        &vertices[v].succs
+                    };
+                    if neighbors.is_empty() {
+                        vertices[v].x
+                    } else {
+                        neighbors.iter().map(|&u| vertices[u].x).sum::<f64>()
+                            / neighbors.len() as f64
+                    }
+                })
+                .collect();
+
+            // 左から詰めた後、右から詰め直して平均をとる
+            let mut left = desired.clone();
+            for i in 1..layer.len() {
+                let min = left[i - 1]
+                    + (vertices[layer[i - 1]].w + vertices[layer[i]].w) / 2.0
+                    + NODE_GAP;
+                left[i] = left[i].max(min);
+            }
+            let mut right = desired.clone();
+            for i in (0..layer.len().saturating_sub(1)).rev() {
+                let max = right[i + 1]
+                    - (vertices[layer[i + 1]].w + vertices[layer[i]].w) / 2.0
+                    - NODE_GAP;
+                right[i] = right[i].min(max);
+            }
+            for (i, &v) in layer.iter().enumerate() {
+                vertices[v].x = (left[i] + right[i]) / 2.0;
+            }
+            // 平均で重なった分を左から押し出す
+            for i in 1..layer.len() {
+                let min = vertices[layer[i - 1]].x
+                    + (vertices[layer[i - 1]].w + vertices[layer[i]].w) / 2.0
+                    + NODE_GAP;
+                if vertices[layer[i]].x < min {
+                    vertices[layer[i]].x = min;
+                }
+            }
+        }
+    }
+}
+
+/// ダミーノードの列に沿って直交する折れ線を引く
+///
+/// ノードの上下の辺に出入りする位置を辺の数だけ等分し、層の間で水平に曲がる
+fn route_edges(
+    vertices: &[Vertex],
+    chains: &[Vec<usize>],
+    nodes: &[Rect],
+    tops: &[f64],
+    heights: &[f64],
+) -> Vec<Vec<Point>> {
+    // ノードごとに出ていく辺と入ってくる辺を相手のx座標順に並べる
+    let mut outs: Vec<Vec<(f64, usize)>> = vec![vec![]; vertices.len()];
+    let mut ins: Vec<Vec<(f64, usize)>> = vec![vec![]; vertices.len()];
+    for (i, chain) in chains.iter().enumerate() {
+        if chain.len() < 2 {
+            continue;
+        }
+        outs[chain[0]].push((vertices[chain[1]].x, i));
+        ins[chain[chain.len() - 1]].push((vertices[chain[chain.len() - 2]].x, i));
+    }
+    let port = |ports: &Vec<(f64, usize)>, edge: usize, v: usize| -> f64 {
+        let mut sorted = ports.clone();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let i = sorted.iter().position(|&(_, e)| e == edge).unwrap();
+        let left = vertices[v].x - vertices[v].w / 2.0;
+        return left + vertices[v].w * (i + 1) as f64 / (sorted.len() + 1) as f64;
+    };
+
+    // 層の間で水平に曲がる高さを辺ごとにずらす
+    let mut bends: Vec<Vec<usize>> = vec![vec![]; tops.len()];
+    for (i, chain) in chains.iter().enumerate() {
+        for &v in &chain[..chain.len() - 1] {
+            bends[vertices[v].layer].push(i);
+        }
+    }
+    let bend_y = |layer: usize, edge: usize| -> f64 {
+        let i = bends[layer].iter().position(|&e| e == edge).unwrap_or(0);
+        let bottom = tops[layer] + heights[layer];
+        return bottom + LAYER_GAP * (i + 1) as f64 / (bends[layer].len() + 1) as f64;
+    };
+
+    let mut routes = vec![];
+    for (i, chain) in chains.iter().enumerate() {
+        if chain.len() < 2 {
+            // 自己ループはノードの右側を回る
+            let r = nodes[chain[0]];
+            let x = r.x + r.w;
+            routes.push(vec![
+                (x, r.y + r.h * 0.7),
+                (x + LOOP_WIDTH, r.y + r.h * 0.7),
+                (x + LOOP_WIDTH, r.y + r.h * 0.3),
+                (x, r.y + r.h * 0.3),
+            ]);
+            continue;
+        }
+        let first = chain[0];
+        let last = chain[chain.len() - 1];
+        let mut x = port(&outs[first], i, first);
+        let mut points = vec![(x, tops[vertices[first].layer] + vertices[first].h)];
+        for (k, &v) in chain.iter().enumerate().skip(1) {
+            let prev_layer = vertices[chain[k - 1]].layer;
+            let target_x = if v == last {
+                port(&ins[last], i, last)
+            } else {
+                vertices[v].x
+            };
+            let y = bend_y(prev_layer, i);
+            if (target_x - x).abs() > 0.5 {
+                points.push((x, y));
+                points.push((target_x, y));
+            }
+            x = target_x;
+            if v == last {
+                points.push((x, tops[vertices[v].layer]));
+            } else {
+                // ダミーノードは層の中を真っ直ぐ通り抜ける
+                let layer = vertices[v].layer;
+                points.push((x, tops[layer] + heights[layer]));
+            }
+        }
+        routes.push(points);
+    }
+    return routes;
+}
+
+#[test]
+fn test_layout() {
+    // 0 -> 1 -> 2 -> 1 (ループ), 0 -> 2, 2 -> 2
+    let sizes = vec![(40.0, 20.0), (60.0, 30.0), (40.0, 20.0)];
+    let edges = vec![(0, 1), (1, 2), (2, 1), (0, 2), (2, 2)];
+    let l = layout(&sizes, &edges);
+
+    assert!(l.nodes[0].y < l.nodes[1].y && l.nodes[1].y < l.nodes[2].y);
+    assert_eq!(l.nodes[1].w, 60.0);
+    // 折れ線は水平か垂直の線分だけでできている
+    for points in &l.edges {
+        for pair in points.windows(2) {
+            assert!(pair[0].0 == pair[1].0 || pair[0].1 == pair[1].1);
+        }
+    }
+    // 後退辺は始点(ノード2)から終点(ノード1)へ向かう
+    let back = &l.edges[2];
+    assert_eq!(back[0].1, l.nodes[2].y);
+    assert_eq!(back[back.len() - 1].1, l.nodes[1].y + l.nodes[1].h);
+    // 0 -> 2はダミーノードを通って層1を縦に抜ける
+    assert!(l.edges[3].len() >= 3);
+    assert!(l.width >= l.nodes[2].x + l.nodes[2].w + LOOP_WIDTH);
+}
@@ -0,0 +1,50 @@
+//! GameBoyのROMファイルやRGBASMのソースからフローチャートを作る
+//!
+//! 入力を解析して図を出力するまでは`Project`から行う
+//!
+//! ```no_run
+//! use flowchartgb::{Format, Graph, Project};
+//!
+//! let project = Project::from_asm("examples/hello/hello.asm")
+//!     .entry("start")
+//!     .output_dir("out")
+//!     .analyze()?;
+//! project.write(Graph::Cfg, Format::Dot)?;
+//! # Ok::<(), flowchartgb::Error>(())
+//! ```
+//!
+//! 個々の段階は`Parser`(asmの構文解析)、`Analyzer`(ファイル全体の解析と出力)、
+//! `Cfg`(制御フローグラフ)、`SymbolTable`(EQUなどのシンボル)を直接使うこともできる
+
+// 値を返すときは明示的に`return`を書く
+#![allow(clippy::needless_return)]
+
+pub mod analyzer;
+pub mod callgraph;
+pub mod cfg;
+pub mod diagnostic;
+pub mod disasm;
+pub mod dot;
+pub mod error;
+pub mod expr;
+pub mod header;
+pub mod html;
+mod layout;
+pub mod mbc;
+pub mod mermaid;
+pub mod model;
+pub mod parser;
+pub mod project;
+pub mod svg;
+pub mod symbol;
+mod variable;
+
+pub use analyzer::Analyzer;
+pub use callgraph::CallGraph;
+pub use cfg::Cfg;
+pub use diagnostic::{Diagnostic, Span};
+pub use error::{Error, Result};
+pub use model::Analysis;
+pub use parser::Parser;
+pub use project::{Analyzed, Format, Graph, Project};
+pub use symbol::SymbolTable;
@@ -1,66 +1,132 @@
+// 値を返すときは明示的に`return`を書く
+#![allow(clippy::needless_return)]
+
 use std::env;
 use std::process::exit;
 
-mod analyzer;
+mod cli;
 mod dialog;
-mod disasm;
-mod parser;
-mod variable;
 
-use std::path::Path;
+use cli::{exit_code, Command};
+use flowchartgb::Project;
+use std::path::PathBuf;
 
 fn main() {
     exit(run());
 }
 
 fn run() -> i32 {
-    let cd_path = env::current_dir().unwrap();
-    let cd = cd_path.to_str().unwrap();
-    // println!("The current directory is {}", cd);
+    let args: Vec<String> = env::args().skip(1).collect();
+    let opts = match cli::parse_args(&args) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            return exit_code::USAGE;
+        }
+    };
 
     // 開始地点となるファイルを受け取る
-    let file_path = dialog::open_file_dialog();
-
-    // 解析結果格納するフォルダを産出する
-    let tmp = file_path.clone();
-    let path = Path::new(&tmp);
-    let extension = path.extension();
-    let outputdir = path.parent().unwrap().to_str().unwrap();
-
-    match extension {
-        Some(ext) if ext == "gb" || ext == "gbc" => {
-            // 逆アセンブルする
-            disasm::disassemble(&file_path);
-            let file_path = cd.to_string() + "\\disassembly\\game.asm";
-            let init_label = "Jump_000_0150";
-
-            // 解析を開始する
-            let mut a = analyzer::Analyzer::new();
-            a.init_analyze(vec![file_path]);
-
-            // 解析結果をダンプする
-            a.dump_flowchart(outputdir, init_label);
-            return 0;
+    let file_path = match (opts.command, &opts.input) {
+        (Command::Help, _) => {
+            println!("{}", cli::USAGE);
+            return exit_code::SUCCESS;
         }
-        Some(ext) if ext == "asm" => {
-            // コマンドライン引数をパースする
-            let args: Vec<String> = env::args().collect();
-            if args.len() <= 1 {
-                println!("If a target is asm file, a label for the starting point is needed.");
-                return 1;
-            }
-            let init_label = &args[1];
+        (Command::Gui, _) => PathBuf::from(dialog::open_file_dialog()),
+        (_, Some(input)) => input.clone(),
+        (_, None) => {
+            eprintln!("error: no input file given\n\n{}", cli::USAGE);
+            return exit_code::USAGE;
+        }
+    };
+    if file_path.as_os_str().is_empty() {
+        eprintln!("error: no file selected");
+        return exit_code::FAILURE;
+    }
 
-            // 解析を開始する
-            let mut a = analyzer::Analyzer::new();
-            a.init_analyze(vec![file_path]);
+    let is_rom = match file_path.extension() {
+        Some(ext) if ext == "gb" || ext == "gbc" => true,
+        Some(ext) if ext == "asm" => false,
+        _ => {
+            eprintln!(
+                "error: {} is neither a gb/gbc nor an asm file",
+                file_path.display()
+            );
+            return exit_code::FAILURE;
+        }
+    };
+    match opts.command {
+        Command::Rom if !is_rom => {
+            eprintln!("error: {} is not a gb/gbc file", file_path.display());
+            return exit_code::USAGE;
+        }
+        Command::Asm if is_rom => {
+            eprintln!("error: {} is not an asm file", file_path.display());
+            return exit_code::USAGE;
+        }
+        _ => {}
+    }
 
-            // 解析結果をダンプする
-            a.dump_flowchart(outputdir, init_label);
-            return 0;
+    // 解析を開始する
+    let mut project = if is_rom {
+        Project::from_rom(&file_path)
+    } else {
+        let mut project = Project::from_asm(&file_path).entry_all_exported(opts.all_exported);
+        for (name, value) in &opts.defines {
+            project = project.define(name, value);
         }
-        _ => {
-            return 1;
+        project
+    };
+    for label in &opts.entries {
+        project = project.entry(label);
+    }
+    // 指定がなければ入力ファイルと同じフォルダに出力する
+    let project = match &opts.output {
+        Some(dir) => project.output_dir(dir),
+        None => project,
+    };
+    let project = project.split(opts.split).per_entry(opts.per_entry);
+    let verb = if is_rom { "disassemble" } else { "analyze" };
+    println!("{} {}...", verb, file_path.display());
+    let project = match project.analyze() {
+        Ok(project) => project,
+        Err(e @ flowchartgb::Error::InvalidDefinition(_)) => {
+            // -Dで定義できなかった
+            eprintln!("error: {}", e);
+            return exit_code::USAGE;
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return exit_code::FAILURE;
         }
+    };
+    for diagnostic in project.render_diagnostics() {
+        eprintln!("{}", diagnostic);
+    }
+    if project.entries().is_empty() {
+        eprintln!("error: no starting point is found, please give one with --entry");
+        return exit_code::FAILURE;
+    }
+    if opts.entries.is_empty() {
+        println!("start from {}...", project.entries().join(", "));
+    }
+
+    // 解析結果をダンプする
+    let mut paths = match project.write(opts.graph, opts.format) {
+        Ok(paths) => paths,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return exit_code::FAILURE;
+        }
+    };
+    if opts.split {
+        // ルーチンごとの図は数だけ表示する
+        let (indexes, charts): (Vec<_>, Vec<_>) =
+            paths.into_iter().partition(|p| p.ends_with("index.md"));
+        println!("dump {} charts...", charts.len());
+        paths = indexes;
+    }
+    for path in paths {
+        println!("dump into {}...", path.display());
     }
+    return exit_code::SUCCESS;
 }
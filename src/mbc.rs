@@ -0,0 +1,147 @@
+extern crate serde;
+use serde::{Deserialize, Serialize};
+
+pub const BANK_SIZE: usize = 0x4000;
+
+/// カートリッジのメモリバンクコントローラ
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Mbc {
+    /// ROM ONLY (バンク切り替えなし)
+    None,
+    Mbc1,
+    Mbc2,
+    Mbc3,
+    Mbc5,
+    /// MMM01、HuC1などその他のコントローラ(MBC1互換とみなす)
+    Other,
+}
+
+impl Mbc {
+    /// ヘッダ$0147のカートリッジタイプから判定する
+    pub fn from_cartridge_type(cartridge_type: u8) -> Self {
+        match cartridge_type {
+            0x00 | 0x08 | 0x09 => Mbc::None,
+            0x01..=0x03 => Mbc::Mbc1,
+            0x05 | 0x06 => Mbc::Mbc2,
+            0x0F..=0x13 => Mbc::Mbc3,
+            0x19..=0x1E => Mbc::Mbc5,
+            _ => Mbc::Other,
+        }
+    }
+
+    /// `address`に`value`を書き込んだときに選択されるROMXバンク
+    ///
+    /// バンク選択レジスタへの書き込みでなければNoneを返す
+    pub fn select_bank(&self, address: u16, value: u8, current: Option<usize>) -> Option<usize> {
+        let value = value as usize;
+        match self {
+            Mbc::None => None,
+            Mbc::Mbc1 | Mbc::Other => match address {
+                0x2000..=0x3FFF => Some((value & 0x1F).max(1)),
+                _ => None,
+            },
+            // MBC2はアドレスのbit8が立っているときにROMバンクを選択する
+            Mbc::Mbc2 => match address {
+                0x0000..=0x3FFF if address & 0x0100 != 0 => Some((value & 0x0F).max(1)),
+                _ => None,
+            },
+            Mbc::Mbc3 => match address {
+                0x2000..=0x3FFF => Some((value & 0x7F).max(1)),
+                _ => None,
+            },
+            // MBC5は下位8bitと9bit目を別のレジスタで選択する(バンク0も選択できる)
+            Mbc::Mbc5 => {
+                let current = current.unwrap_or(1);
+                match address {
+                    0x2000..=0x2FFF => Some((current & 0x100) | value),
+                    0x3000..=0x3FFF => Some((current & 0xFF) | (value & 1) << 8),
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+
+/// バンク番号付きのアドレス
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BankedAddress {
+    pub bank: usize,
+    pub address: u16,
+}
+
+impl BankedAddress {
+    /// ROMのオフセットから変換する
+    pub fn from_offset(offset: usize) -> Self {
+        let bank = offset / BANK_SIZE;
+        let address = if bank == 0 {
+            offset
+        } else {
+            BANK_SIZE + offset % BANK_SIZE
+        };
+        return Self {
+            bank,
+            address: address as u16,
+        };
+    }
+
+    /// `bank`のコードから`address`を参照したときの飛び先
+    ///
+    /// ROMXへの参照は`romx`(選択中のバンク)に解決し、不明ならバンク0からはバンク1、
+    /// ROMXからは自分自身のバンクとみなす。ROM外のアドレスはNone
+    ///
+    /// MBC5でバンク0を選んでいれば、ROMXの領域にはバンク0が見えるので$0000-$3FFFに直す
+    pub fn resolve(bank: usize, address: u16, romx: Option<usize>) -> Option<Self> {
+        match address {
+            0x0000..=0x3FFF => Some(Self { bank: 0, address }),
+            0x4000..=0x7FFF => {
+                let bank = if bank == 0 { romx.unwrap_or(1) } else { bank };
+                if bank == 0 {
+                    let address = address - BANK_SIZE as u16;
+                    return Some(Self { bank, address });
+                }
+                Some(Self { bank, address })
+            }
+            _ => None,
+        }
+    }
+
+    /// ROMのオフセット
+    pub fn offset(&self) -> usize {
+        if self.address < BANK_SIZE as u16 {
+            return self.address as usize;
+        }
+        return self.bank * BANK_SIZE + (self.address as usize - BANK_SIZE);
+    }
+}
+
+#[test]
+fn test_select_bank() {
+    assert_eq!(Mbc::from_cartridge_type(0x13), Mbc::Mbc3);
+    assert_eq!(Mbc::Mbc1.select_bank(0x2000, 0x00, None), Some(1));
+    assert_eq!(Mbc::Mbc1.select_bank(0x2100, 0x25, None), Some(5));
+    assert_eq!(Mbc::Mbc1.select_bank(0x4000, 0x02, None), None);
+    assert_eq!(Mbc::Mbc2.select_bank(0x2100, 0x03, None), Some(3));
+    assert_eq!(Mbc::Mbc2.select_bank(0x2000, 0x03, None), None);
+    assert_eq!(Mbc::Mbc3.select_bank(0x3FFF, 0x45, None), Some(0x45));
+    assert_eq!(Mbc::Mbc5.select_bank(0x2000, 0x00, None), Some(0));
+    assert_eq!(Mbc::Mbc5.select_bank(0x3000, 0x01, Some(0x12)), Some(0x112));
+    assert_eq!(Mbc::None.select_bank(0x2000, 0x02, None), None);
+
+    let a = BankedAddress::from_offset(0x1_4123);
+    assert_eq!((a.bank, a.address), (5, 0x4123));
+    assert_eq!(a.offset(), 0x1_4123);
+    assert_eq!(
+        BankedAddress::resolve(0, 0x4000, Some(3)).unwrap().offset(),
+        0xC000
+    );
+    assert_eq!(BankedAddress::resolve(2, 0x4000, Some(3)).unwrap().bank, 2);
+    // MBC5でバンク0を選んだときのROMXの領域
+    assert_eq!(
+        BankedAddress::resolve(0, 0x4123, Some(0)),
+        Some(BankedAddress {
+            bank: 0,
+            address: 0x0123
+        })
+    );
+    assert_eq!(BankedAddress::resolve(2, 0x8000, None), None);
+}
@@ -0,0 +1,30 @@
+//! Mermaid形式の出力に使う関数
+
+/// `"..."`で囲んだラベルに入れられるようにエスケープする(改行は`<br/>`にする)
+pub fn escape(s: &str) -> String {
+    let mut escaped = "".to_string();
+    for (i, line) in s.lines().enumerate() {
+        if i > 0 {
+            escaped += "<br/>";
+        }
+        for c in line.chars() {
+            match c {
+                '"' => escaped += "#quot;",
+                '#' => escaped += "#35;",
+                '<' => escaped += "#lt;",
+                '>' => escaped += "#gt;",
+                '\t' => escaped += "    ",
+                _ => escaped.push(c),
+            }
+        }
+    }
+    return escaped;
+}
+
+#[test]
+fn test_escape() {
+    assert_eq!(
+        escape("ld a,\"#\"\n<x>"),
+        "ld a,#quot;#35;#quot;<br/>#lt;x#gt;"
+    );
+}
@@ -0,0 +1,501 @@
+//! json出力用の解析結果のモデル
+//!
+//! `schema_version`が変わらない限り、フィールドの削除や意味の変更はしない
+//! (フィールドの追加は互換性のある変更とする)
+
+use std::collections::{HashMap, HashSet};
+
+extern crate serde;
+extern crate serde_json;
+use serde::{Deserialize, Serialize};
+
+use super::callgraph::{CallGraph, Routine};
+use super::cfg::{BlockId, Cfg, EdgeKind};
+use super::diagnostic::{Diagnostic, Span};
+use super::disasm::bank_of_file;
+use super::error::{Error, Result};
+use super::expr::{self, Value};
+use super::header::Header;
+use super::mbc::BANK_SIZE;
+use super::parser::{Element, Nodes, SectionDirective, SectionModifier};
+use super::symbol::{Location, SymbolKind, SymbolTable};
+
+/// jsonのスキーマのバージョン
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// 解析結果全体
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Analysis {
+    pub schema_version: u32,
+    /// 出力したツールとそのバージョン
+    pub generator: String,
+    /// ROMのヘッダ(asmファイルを解析した場合はNone)
+    pub header: Option<Header>,
+    /// 開始ラベル
+    pub entries: Vec<String>,
+    pub files: Vec<SourceFile>,
+    pub sections: Vec<Section>,
+    pub blocks: Vec<Block>,
+    pub edges: Vec<Edge>,
+    /// ルーチンと呼び出し関係
+    pub routines: Vec<Routine>,
+    pub constants: Vec<Constant>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum FileKind {
+    /// 解析したアセンブリファイル
+    Asm,
+    /// INCLUDEされたアセンブリ以外のファイル
+    Other,
+}
+
+/// 入力のファイル(パスは最初のファイルのディレクトリからの相対パス)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SourceFile {
+    pub path: String,
+    pub kind: FileKind,
+    pub labels: Vec<Label>,
+}
+
+/// ラベルとそのラベルから次のラベルまでの行
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Label {
+    /// ローカルラベルは`global/.local`、条件分岐の後ろは`label'`の形になる
+    pub name: String,
+    pub global: String,
+    pub lines: Vec<Line>,
+    /// 次に実行するラベル
+    pub next: Option<String>,
+    /// 条件分岐が成立したときに実行するラベル
+    pub next_cond: Option<String>,
+    /// 呼び出すラベル
+    pub calls: Vec<String>,
+    /// ラベルを定義した位置
+    #[serde(default)]
+    pub span: Option<Span>,
+    /// ラベルを置いたSECTION(ROMではバンクのファイル名`bank_001`)
+    #[serde(default)]
+    pub section: Option<String>,
+    /// ラベルを置いたバンク(ROMXでバンクを指定していないSECTIONなどはNone)
+    #[serde(default)]
+    pub bank: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum LineKind {
+    Instruction,
+    Comment,
+    /// データ定義やディレクティブなど
+    Text,
+    Include,
+    Macro,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Line {
+    pub kind: LineKind,
+    pub text: String,
+    /// 命令(Instruction)かマクロ名(Macro)
+    pub op: Option<String>,
+    pub operands: Vec<String>,
+    /// オペランドを評価した値(レジスタや評価できないものはNone)
+    #[serde(default)]
+    pub values: Vec<Option<i64>>,
+    pub comment: Option<String>,
+    /// INCLUDEするファイル
+    pub include: Option<String>,
+    /// マクロを展開した命令なら、マクロを呼び出した行(1始まり)
+    #[serde(default)]
+    pub expanded_from: Option<usize>,
+    /// ソース上の位置
+    #[serde(default)]
+    pub span: Option<Span>,
+}
+
+/// ROMのバンクやSECTIONなど、配置先の決まったコードのまとまり
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Section {
+    pub name: String,
+    /// ROM0, ROMX, WRAM0など
+    pub kind: String,
+    pub bank: Option<usize>,
+    pub address: Option<u16>,
+    pub file: String,
+    /// `ALIGN[bits, offset]`
+    #[serde(default)]
+    pub align: Option<u8>,
+    #[serde(default)]
+    pub align_offset: Option<u16>,
+    #[serde(default)]
+    pub modifier: SectionModifier,
+}
+
+/// 基本ブロック
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Block {
+    pub id: BlockId,
+    pub label: String,
+    /// ブロックを含むラベルの`Label::name`
+    pub node: String,
+    pub file: String,
+    pub global: String,
+    /// ブロックに含まれる`Label::lines`の範囲(endは含まない)
+    pub start: usize,
+    pub end: usize,
+    pub conditional: bool,
+}
+
+/// 基本ブロック間の辺(Callの辺も含む)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Edge {
+    pub from: BlockId,
+    /// 飛び先のブロック(Returnや、解析したコード内に見つからない場合はNone)
+    pub to: Option<BlockId>,
+    pub kind: EdgeKind,
+    pub label: String,
+    pub condition: Option<String>,
+}
+
+/// EQUなどで定義した定数(ラベル以外のシンボル)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Constant {
+    pub name: String,
+    #[serde(default)]
+    pub kind: SymbolKind,
+    /// 評価できなかった場合や文字列の場合はNone
+    pub value: Option<i64>,
+    pub expression: String,
+    pub file: String,
+    #[serde(default)]
+    pub line: Option<usize>,
+    /// SETなどで再定義した場所
+    #[serde(default)]
+    pub redefinitions: Vec<Location>,
+}
+
+impl Analysis {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        asm: &HashMap<String, Nodes>,
+        cfg: &Cfg,
+        text: &HashSet<String>,
+        header: &Option<Header>,
+        entries: &[String],
+        symbols: &SymbolTable,
+        diagnostics: &[Diagnostic],
+        directives: &[SectionDirective],
+    ) -> Self {
+        let mut paths: Vec<&String> = asm.keys().collect();
+        paths.sort();
+
+        let constants = symbols
+            .symbols()
+            .into_iter()
+            .filter(|s| s.kind != SymbolKind::Label)
+            .map(|s| Constant {
+                name: s.name.clone(),
+                kind: s.kind,
+                value: match &s.value {
+                    Some(Value::Int(v)) => Some(*v),
+                    _ => None,
+                },
+                expression: s.expression.clone(),
+                file: s.location.file.clone(),
+                line: s.location.line,
+                redefinitions: s.redefinitions.clone(),
+            })
+            .collect();
+
+        let mut files: Vec<SourceFile> = paths
+            .iter()
+            .map(|path| {
+                let nodes = &asm[*path];
+                let mut names: Vec<&String> = nodes.keys().collect();
+                names.sort();
+                SourceFile {
+                    path: path.to_string(),
+                    kind: FileKind::Asm,
+                    labels: names
+                        .into_iter()
+                        .map(|name| {
+                            let node = &nodes[name];
+                            let section = match (&node.section, header) {
+                                (Some(section), _) => Some(section.clone()),
+                                (None, Some(_)) => {
+                                    bank_of_file(path).map(|b| format!("bank_{:03x}", b))
+                                }
+                                (None, None) => None,
+                            };
+                            let bank = match &node.section {
+                                Some(section) => directives
+                                    .iter()
+                                    .find(|d| &d.name == section)
+                                    .and_then(|d| d.bank()),
+                                None if header.is_some() => bank_of_file(path),
+                                None => None,
+                            };
+                            Label {
+                                name: name.clone(),
+                                global: node.global.clone(),
+                                lines: node
+                                    .elements
+                                    .iter()
+                                    .map(|e| Line::from(e).resolve(symbols))
+                                    .collect(),
+                                next: non_empty(&node.next),
+                                next_cond: non_empty(&node.next_cond),
+                                calls: node.calls.clone(),
+                                span: node.span.clone(),
+                                section,
+                                bank,
+                            }
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+        let mut others: Vec<&String> = text.iter().collect();
+        others.sort();
+        for path in others {
+            files.push(SourceFile {
+                path: path.clone(),
+                kind: FileKind::Other,
+                labels: vec![],
+            });
+        }
+
+        // ROMを逆アセンブルした場合はバンクをセクションとする
+        let mut sections = vec![];
+        if header.is_some() {
+            for path in &paths {
+                if let Some(bank) = bank_of_file(path) {
+                    sections.push(Section {
+                        name: format!("bank_{:03x}", bank),
+                        kind: if bank == 0 { "ROM0" } else { "ROMX" }.to_string(),
+                        bank: Some(bank),
+                        address: Some(if bank == 0 { 0 } else { BANK_SIZE as u16 }),
+                        file: path.to_string(),
+                        align: None,
+                        align_offset: None,
+                        modifier: SectionModifier::Normal,
+                    });
+                }
+            }
+        }
+        for directive in directives {
+            sections.push(Section {
+                name: directive.name.clone(),
+                kind: directive.kind.clone(),
+                bank: directive.bank(),
+                address: directive.address,
+                file: directive.file.clone(),
+                align: directive.align,
+                align_offset: directive.align_offset,
+                modifier: directive.modifier,
+            });
+        }
+
+        let mut blocks = vec![];
+        let mut edges = vec![];
+        for block in cfg.blocks() {
+            blocks.push(Block {
+                id: block.id,
+                label: block.label.clone(),
+                node: block.node.clone(),
+                file: block.file.clone(),
+                global: block.global.clone(),
+                start: block.instructions.start,
+                end: block.instructions.end,
+                conditional: block.is_conditional(),
+            });
+            for edge in &block.successors {
+                edges.push(Edge {
+                    from: block.id,
+                    to: edge.to,
+                    kind: edge.kind,
+                    label: edge.label.clone(),
+                    condition: edge.condition.clone(),
+                });
+            }
+        }
+        let routines = CallGraph::build(asm, cfg, entries).into_routines();
+
+        return Self {
+            schema_version: SCHEMA_VERSION,
+            generator: format!("flowchartgb {}", env!("CARGO_PKG_VERSION")),
+            header: header.clone(),
+            entries: entries.to_vec(),
+            files,
+            sections,
+            blocks,
+            edges,
+            routines,
+            constants,
+            diagnostics: diagnostics.to_vec(),
+        };
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        let json = serde_json::to_string(self).map_err(|e| Error::Serialize(e.to_string()))?;
+        return Ok(json + "\n"); // "\n"を足しとかないとvscodeの自動整形機能でjsonが壊れる
+    }
+
+    /// jsonから読み込む(`schema_version`が違う場合はエラーにする)
+    pub fn from_json(json: &str) -> Result<Self> {
+        let parse_error = |e: serde_json::Error| Error::Parse(e.to_string());
+        let value: serde_json::Value = serde_json::from_str(json).map_err(parse_error)?;
+        match value.get("schema_version").and_then(|v| v.as_u64()) {
+            Some(version) if version == SCHEMA_VERSION as u64 => {}
+            Some(version) => {
+                return Err(Error::Parse(format!(
+                    "unsupported schema version {} (expected {})",
+                    version, SCHEMA_VERSION
+                )))
+            }
+            None => return Err(Error::Parse("schema_version is missing".to_string())),
+        }
+        return serde_json::from_value(value).map_err(parse_error);
+    }
+}
+
+impl From<&Element> for Line {
+    fn from(element: &Element) -> Self {
+        let mut line = Line {
+            kind: LineKind::Text,
+            text: element.text().to_string(),
+            op: None,
+            operands: vec![],
+            values: vec![],
+            comment: None,
+            include: None,
+            expanded_from: None,
+            span: element.span().cloned(),
+        };
+        match element {
+            Element::Opcode {
+                op,
+                operand1,
+                operand2,
+                comment,
+                expanded_from,
+                ..
+            } => {
+                line.kind = if op.is_empty() {
+                    LineKind::Comment
+                } else {
+                    LineKind::Instruction
+                };
+                line.op = non_empty(op);
+                line.operands = [operand1, operand2]
+                    .iter()
+                    .filter(|o| !o.is_empty())
+                    .map(|o| o.to_string())
+                    .collect();
+                line.comment = non_empty(comment);
+                line.expanded_from = *expanded_from;
+            }
+            Element::Text { .. } => {}
+            Element::Include { target, .. } => {
+                line.kind = LineKind::Include;
+                line.include = Some(target.clone());
+            }
+            Element::Macro { label, .. } => {
+                line.kind = LineKind::Macro;
+                line.op = Some(label.clone());
+            }
+        }
+        return line;
+    }
+}
+
+impl Line {
+    /// 命令のオペランドを定数で評価する
+    fn resolve(mut self, symbols: &SymbolTable) -> Self {
+        if self.kind == LineKind::Instruction {
+            self.values = self
+                .operands
+                .iter()
+                .map(|o| expr::operand_value(o, symbols))
+                .collect();
+        }
+        return self;
+    }
+}
+
+fn non_empty(s: &str) -> Option<String> {
+    if s.is_empty() {
+        return None;
+    }
+    return Some(s.to_string());
+}
+
+#[test]
+fn test_analysis_json() {
+    let text = "rLY EQU $FF44
+start:
+\tld a, 1 ; one
+\tld [rLY], a
+.loop
+\tjr nz, .loop
+\tcall sub
+\tret
+SECTION \"Sub\", ROM0, ALIGN[4]
+sub:
+\tret
+";
+    let (asm, _, mut p) = super::parser::parse_snippet(text);
+    let symbols = p.take_symbols();
+    let mut text = HashSet::new();
+    text.insert("font.chr".to_string());
+    let diagnostics = vec![Diagnostic::warning("test")];
+    let analysis = Analysis::new(
+        &asm,
+        &Cfg::build(&asm),
+        &text,
+        &None,
+        &["start".to_string()],
+        &symbols,
+        &diagnostics,
+        &p.take_sections(),
+    );
+
+    assert_eq!(analysis.files.len(), 2);
+    assert_eq!(analysis.files[1].kind, FileKind::Other);
+    let start = analysis.files[0]
+        .labels
+        .iter()
+        .find(|l| l.name == "start")
+        .unwrap();
+    assert_eq!(start.lines[0].kind, LineKind::Instruction);
+    assert_eq!(start.lines[0].operands, vec!["a", "1"]);
+    assert_eq!(start.lines[0].comment, Some("; one".to_string()));
+    assert_eq!(start.lines[1].values, vec![Some(0xFF44), None]);
+    let span = start.lines[0].span.as_ref().unwrap();
+    assert_eq!((span.line, span.column), (3, 2));
+    assert_eq!(start.span.as_ref().unwrap().line, 2);
+    assert_eq!(analysis.constants[0].name, "rLY");
+    assert_eq!(analysis.constants[0].value, Some(0xFF44));
+    assert_eq!(analysis.constants[0].line, Some(1));
+    assert_eq!(start.next, Some("start/.loop".to_string()));
+    assert!(analysis
+        .edges
+        .iter()
+        .any(|e| e.kind == EdgeKind::Call && e.label == "sub"));
+    assert_eq!(analysis.routines.len(), 2);
+    assert_eq!((&start.section, start.bank), (&None, None));
+    let sub = analysis.files[0]
+        .labels
+        .iter()
+        .find(|l| l.name == "sub")
+        .unwrap();
+    assert_eq!((sub.section.as_deref(), sub.bank), (Some("Sub"), Some(0)));
+    assert_eq!(analysis.sections[0].align, Some(4));
+
+    let json = analysis.to_json().unwrap();
+    assert!(json.starts_with("{\"schema_version\":1,"));
+    assert_eq!(Analysis::from_json(&json).unwrap(), analysis);
+    let newer = json.replacen("\"schema_version\":1", "\"schema_version\":99", 1);
+    assert!(Analysis::from_json(&newer).is_err());
+}
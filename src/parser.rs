@@ -1,74 +1,292 @@
 use std::collections::{HashMap, HashSet};
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 extern crate serde;
 extern crate serde_json;
 use serde::{Deserialize, Serialize};
 
+use super::diagnostic::{Diagnostic, Span};
+use super::error::{self, Error};
+use super::expr::{self, Env};
+use super::symbol::{Location, SymbolTable};
 use super::variable::opcode;
 use super::variable::token;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
-enum Element {
+pub enum Element {
     Opcode {
         text: String,
         op: String,
         operand1: String,
         operand2: String,
         comment: String,
+        /// マクロを展開した命令なら、マクロを呼び出した行(1始まり)
+        #[serde(default)]
+        expanded_from: Option<usize>,
+        #[serde(default)]
+        span: Option<Span>,
+        /// オペランドの飛び先のほかに辿れる飛び先(逆アセンブルでバンクごとに解決した飛び先など)
+        #[serde(default)]
+        alternatives: Vec<String>,
     },
     Text {
         text: String,
+        #[serde(default)]
+        span: Option<Span>,
     },
     Include {
         text: String,
         target: String,
+        #[serde(default)]
+        span: Option<Span>,
     },
     Macro {
         label: String,
         texts: Vec<String>,
         text: String,
+        #[serde(default)]
+        span: Option<Span>,
     },
 }
 
+impl Element {
+    pub fn text(&self) -> &str {
+        match self {
+            Element::Include { text, .. }
+            | Element::Opcode { text, .. }
+            | Element::Text { text, .. }
+            | Element::Macro { text, .. } => text,
+        }
+    }
+
+    /// ソース上の位置(逆アセンブルした命令などソースのない場合はNone)
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Element::Include { span, .. }
+            | Element::Opcode { span, .. }
+            | Element::Text { span, .. }
+            | Element::Macro { span, .. } => span.as_ref(),
+        }
+    }
+
+    fn set_span(&mut self, to: Option<Span>) {
+        match self {
+            Element::Include { span, .. }
+            | Element::Opcode { span, .. }
+            | Element::Text { span, .. }
+            | Element::Macro { span, .. } => *span = to,
+        }
+    }
+
+    /// 命令なら(命令, 第1オペランド, 第2オペランド)を返す(コメント行は含まない)
+    pub fn instruction(&self) -> Option<(&str, &str, &str)> {
+        match self {
+            Element::Opcode {
+                op,
+                operand1,
+                operand2,
+                ..
+            } if !op.is_empty() => Some((op, operand1, operand2)),
+            _ => None,
+        }
+    }
+
+    /// 命令のオペランドのほかに辿れる飛び先
+    pub fn alternatives(&self) -> &[String] {
+        match self {
+            Element::Opcode { alternatives, .. } => alternatives,
+            _ => &[],
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Node {
     pub global: String,
     pub text: String,
-    elements: Vec<Element>,
+    pub elements: Vec<Element>,
     pub next: String,
     pub next_cond: String,
     pub calls: Vec<String>,
+    /// ラベルを定義した位置(`main`や条件分岐の後ろのノードはNone)
+    #[serde(default)]
+    pub span: Option<Span>,
+    /// ラベルを置いたSECTIONの名前
+    #[serde(default)]
+    pub section: Option<String>,
+}
+
+impl Node {
+    /// textのうち要素より前の部分(ラベル名の行)
+    pub fn header(&self) -> &str {
+        let body: usize = self.elements.iter().map(|e| e.text().len() + 1).sum();
+        return &self.text[..self.text.len() - body];
+    }
 }
 
 pub type Nodes = HashMap<String, Node>;
 
+/// 同じ名前のSECTIONを重ねる(UNION)か続ける(FRAGMENT)か
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SectionModifier {
+    Normal,
+    Union,
+    Fragment,
+}
+
+impl Default for SectionModifier {
+    fn default() -> Self {
+        return SectionModifier::Normal;
+    }
+}
+
+/// SECTIONディレクティブの内容
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SectionDirective {
+    pub name: String,
+    /// ROM0, ROMX, VRAM, SRAM, WRAM0, WRAMX, OAM, HRAMのいずれか
+    pub kind: String,
+    /// `ROM0[$100]`で指定したアドレス
+    pub address: Option<u16>,
+    /// `BANK[1]`で指定したバンク
+    pub bank: Option<usize>,
+    /// `ALIGN[8]`で揃えるアドレスの下位ビット数
+    pub align: Option<u8>,
+    /// `ALIGN[8, 2]`の2つ目(揃えた位置からのオフセット)
+    pub align_offset: Option<u16>,
+    pub modifier: SectionModifier,
+    /// 最初にSECTIONを書いたファイル(`parse_section`では空)
+    pub file: String,
+    /// SECTIONの先頭のノード(`parse_section`では空)
+    #[serde(default)]
+    pub label: String,
+}
+
+impl SectionDirective {
+    /// ラベルを置くバンク(ROM0などバンクのない領域は0、バンクを指定していなければNone)
+    pub fn bank(&self) -> Option<usize> {
+        match &self.kind[..] {
+            "ROM0" | "WRAM0" | "OAM" | "HRAM" => return Some(0),
+            _ => return self.bank,
+        }
+    }
+}
+
+/// MACRO〜ENDMで定義したマクロ
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Macro {
+    pub name: String,
+    /// MACROとENDMの間の行(字下げはそのまま)
+    pub body: Vec<String>,
+    /// 本体で参照している`\1`〜`\9`のうち最大の番号
+    pub params: usize,
+    pub file: String,
+    pub line: usize,
+}
+
+pub type Macros = HashMap<String, Macro>;
+
+/// マクロの展開を入れ子にできる深さ
+const MAX_MACRO_DEPTH: usize = 64;
+
+/// REPTやFORで繰り返せる回数
+const MAX_REPEAT: i64 = 65536;
+
+/// `text`の各行の元になったソースの行
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+struct Origin {
+    /// 1始まりの行番号(展開したマクロの行は呼び出した行)
+    line: usize,
+    /// 展開したマクロの行なら`invocations`の添字
+    invocation: Option<usize>,
+}
+
+/// IF〜ENDCの状態
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+struct Condition {
+    /// 外側の分岐が成立しているか
+    parent: bool,
+    /// 今の分岐が成立しているか
+    active: bool,
+    /// すでに成立した分岐があるか
+    taken: bool,
+}
+
+/// マクロの呼び出し
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Invocation {
+    args: Vec<String>,
+    /// SHIFTでずらした引数の数
+    shift: usize,
+    /// `\@`を置き換える番号
+    unique: usize,
+    depth: usize,
+    /// 呼び出した行のマクロ名の桁(1始まり)
+    column: usize,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Parser {
     text: Vec<String>,
+    origins: Vec<Origin>,
     line: usize,
     nodes: Nodes,
     global: String,
     local: String,
+    /// ローカルラベルを持つグローバルラベル(SECTIONの先頭のノードでは変わらない)
+    scope: String,
     includes: Vec<String>,
-    macros: HashSet<String>,
-    cur_dir: String,
-    base_dir: String,
+    macros: Macros,
+    invocations: Vec<Invocation>,
+    conditions: Vec<Condition>,
+    /// `\@`を置き換えた数
+    unique: usize,
+    cur_dir: PathBuf,
+    base_dir: PathBuf,
+    file: String,
+    symbols: SymbolTable,
+    diagnostics: Vec<Diagnostic>,
+    /// 今のSECTIONの名前
+    section: Option<String>,
+    /// このファイルとINCLUDEしたファイルのSECTION
+    sections: Vec<SectionDirective>,
+    /// SECTIONの先頭に置いたノードの名前
+    section_nodes: HashSet<String>,
 }
 
 impl Parser {
-    pub fn new(file_path: String, base_dir: String, macros: HashSet<String>) -> Self {
-        let file = File::open(&file_path).unwrap();
-        let lines = BufReader::new(file).lines();
-        let (num_of_line, _) = lines.size_hint();
-        let mut text = Vec::with_capacity(num_of_line);
-        for line in lines {
-            let l = line.unwrap();
-            text.push(l);
-        }
+    /// ファイルを読んでParserを生成する(UTF-8でない部分は置き換えて読む)
+    pub fn new(path: &Path, base_dir: &Path, macros: Macros) -> error::Result<Self> {
+        let bytes = fs::read(path).map_err(|e| Error::io(path, e))?;
+        let text = String::from_utf8_lossy(&bytes)
+            .lines()
+            .map(|l| l.to_string())
+            .collect();
+
+        let cur_dir = match path.parent() {
+            Some(dir) => dir.to_path_buf(),
+            None => return Err(Error::invalid_path(path, "has no parent directory")),
+        };
+
+        return Ok(Self::with_text(
+            text,
+            cur_dir,
+            base_dir.to_path_buf(),
+            macros,
+        ));
+    }
 
+    /// ソースファイルを読まずにParserを生成する
+    ///
+    /// 逆アセンブラのように命令を直接組み立てる場合は空の`text`を渡して
+    /// `push_label`、`push_instruction`、`push_text`でノードを構築する
+    pub fn with_text(
+        text: Vec<String>,
+        cur_dir: PathBuf,
+        base_dir: PathBuf,
+        macros: Macros,
+    ) -> Self {
         let global = "main";
         let mut nodes = HashMap::new();
         nodes.insert(
@@ -80,69 +298,96 @@ impl Parser {
                 next: "".to_string(),
                 next_cond: "".to_string(),
                 calls: Default::default(),
+                span: None,
+                section: None,
             },
         );
 
-        let cur_dir = Path::new(&file_path)
-            .parent()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string();
-
+        let origins = (1..=text.len())
+            .map(|line| Origin {
+                line,
+                invocation: None,
+            })
+            .collect();
         return Self {
-            text: text,
+            text,
+            origins,
             line: 0,
-            nodes: nodes,
+            nodes,
             global: global.to_string(),
             local: "".to_string(),
+            scope: global.to_string(),
             includes: Default::default(),
-            macros: macros,
-            cur_dir: cur_dir,
+            macros,
+            invocations: vec![],
+            conditions: vec![],
+            unique: 0,
+            cur_dir,
             base_dir,
+            file: "".to_string(),
+            symbols: Default::default(),
+            diagnostics: vec![],
+            section: None,
+            sections: vec![],
+            section_nodes: Default::default(),
         };
     }
 
-    pub fn parse(&mut self, filename: String) -> (HashMap<String, Nodes>, HashSet<String>) {
+    pub fn parse(&mut self, filename: String) -> (HashMap<String, Nodes>, Macros) {
+        self.file = filename.clone();
         let mut result = HashMap::new();
-        loop {
+        while self.line < self.text.len() {
             let (end, results, macros) = self.parse_element();
-            match results {
-                Some(results) => {
-                    for (filename, nodes) in results {
-                        result.insert(filename, nodes);
-                    }
+            if let Some(results) = results {
+                for (filename, nodes) in results {
+                    result.insert(filename, nodes);
                 }
-                None => {}
             }
-            match macros {
-                Some(macros) => {
-                    for m in macros {
-                        self.macros.insert(m);
-                    }
+            if let Some(macros) = macros {
+                for (name, m) in macros {
+                    self.macros.insert(name, m);
                 }
-                None => {}
             }
             if end {
                 break;
             }
         }
 
+        if !self.conditions.is_empty() {
+            self.warn(&format!("{} without {}", token::IF, token::ENDC));
+            self.conditions.clear();
+        }
+
         let nodes = self.nodes.clone();
         result.insert(filename, nodes);
         return (result, self.macros.clone());
     }
 
-    fn parse_element(
-        &mut self,
-    ) -> (
-        bool,
-        Option<HashMap<String, Nodes>>,
-        Option<HashSet<String>>,
-    ) {
+    fn parse_element(&mut self) -> (bool, Option<HashMap<String, Nodes>>, Option<Macros>) {
+        let invocation = self.origins[self.line].invocation;
+        if let Some(id) = invocation {
+            self.substitute(id);
+        }
         let line = eat_space(&self.text[self.line]).to_string();
-        let tokens: Vec<&str> = line.split(|c| c == ' ' || c == '\t').collect();
+        let tokens: Vec<&str> = line.split([' ', '\t']).collect();
         let first = eat_space(tokens[0]);
+        if self.parse_condition(first, &line) {
+            self.line += 1;
+            return (self.line >= self.text.len(), None, None);
+        }
+        if let (Some(id), true) = (invocation, first.eq_ignore_ascii_case(token::SHIFT)) {
+            let n = tokens
+                .get(1)
+                .and_then(|t| {
+                    expr::eval_str(t, &self.symbols)
+                        .and_then(|v| v.as_int())
+                        .ok()
+                })
+                .unwrap_or(1);
+            self.invocations[id].shift += n.max(0) as usize;
+            self.line += 1;
+            return (self.line >= self.text.len(), None, None);
+        }
         match first {
             t if t.starts_with(";") => {
                 return (self.parse_opcode(&tokens), None, None);
@@ -150,13 +395,29 @@ impl Parser {
             t if opcode::OPCODE_LIST.contains(&&(t.to_ascii_uppercase())[..]) => {
                 return (self.parse_opcode(&tokens), None, None);
             }
-            token::SECTION => {
+            t if t.eq_ignore_ascii_case(token::SECTION) => {
+                self.begin_section(&line);
+                return (self.parse_text(), None, None);
+            }
+            t if t.eq_ignore_ascii_case(token::ENDSECTION) => {
+                self.section = None;
                 return (self.parse_text(), None, None);
             }
             token::EQU | token::SET => {
                 return (self.parse_text(), None, None);
             }
-            "REPT" | "rept" | "ENDR" | "endr" => {
+            t if t.eq_ignore_ascii_case(token::DEF) => {
+                return (self.parse_text(), None, None);
+            }
+            t if t.eq_ignore_ascii_case(token::RSRESET) || t.eq_ignore_ascii_case(token::RSSET) => {
+                return (self.parse_text(), None, None);
+            }
+            t if t.eq_ignore_ascii_case(token::REPT) || t.eq_ignore_ascii_case(token::FOR) => {
+                let first = first.to_ascii_uppercase();
+                return (self.parse_repeat(&first, &line), None, None);
+            }
+            t if t.eq_ignore_ascii_case(token::ENDR) => {
+                self.warn(&format!("{} without {}", token::ENDR, token::REPT));
                 return (self.parse_text(), None, None);
             }
             token::INCLUDE => {
@@ -165,19 +426,49 @@ impl Parser {
             token::INCBIN => {
                 return (self.parse_text(), None, None);
             }
+            t if t.eq_ignore_ascii_case(token::EXPORT) || t.eq_ignore_ascii_case(token::GLOBAL) => {
+                let names = expr::strip_comment(&line)[first.len()..].to_string();
+                for name in split_args(&names) {
+                    self.symbols.export(&name);
+                }
+                return (self.parse_text(), None, None);
+            }
             t if opcode::DEFINE_LIST.contains(&&(t.to_ascii_uppercase())[..]) => {
                 return (self.parse_text(), None, None);
             }
             "" => {
                 return (self.parse_text(), None, None);
             }
-            t if self.macros.contains(t) => {
-                return (self.parse_text(), None, None);
+            t if self.macros.contains_key(t) => {
+                let name = t.to_string();
+                return (self.parse_macro_call(&name), None, None);
+            }
+            t if opcode::is_call(&t.to_ascii_uppercase())
+                || opcode::is_jump(&t.to_ascii_uppercase()) =>
+            {
+                // farcallなどはプロジェクトのマクロがなければ飛び先をそのまま辿る
+                return (self.parse_opcode(&tokens), None, None);
+            }
+            t if t.eq_ignore_ascii_case(token::MACRO) && tokens.len() > 1 => {
+                // MACRO name
+                let name = remove_colon(eat_space(tokens[1]));
+                return (self.parse_macro(name), None, None);
             }
             _ => {
-                if tokens.len() > 1 && eat_space(tokens[1]) == token::MACRO {
-                    return (self.parse_macro(), None, None);
-                } else if &self.text[self.line] != eat_start_space(&self.text[self.line]) {
+                if tokens.len() > 1 && eat_space(tokens[1]).eq_ignore_ascii_case(token::MACRO) {
+                    let name = remove_colon(tokens[0]);
+                    return (self.parse_macro(name), None, None);
+                } else if self.text[self.line] != eat_start_space(&self.text[self.line]) {
+                    if !is_known_directive(first)
+                        && !first.ends_with(':')
+                        && expr::parse_definition(&line).is_none()
+                        && !is_rs_definition(&tokens)
+                    {
+                        self.warn(&format!("unknown mnemonic {}", first));
+                    }
+                    return (self.parse_text(), None, None);
+                } else if expr::parse_definition(&line).is_some() || is_rs_definition(&tokens) {
+                    // `NAME EQU expr`や`NAME RB 1`はシンボルの定義でラベルではない
                     return (self.parse_text(), None, None);
                 } else {
                     return (self.parse_label(&tokens), None, None);
@@ -187,30 +478,56 @@ impl Parser {
     }
 
     /// ラベルのパース
-    fn parse_label(&mut self, tokens: &Vec<&str>) -> bool {
-        let first = tokens[0];
+    fn parse_label(&mut self, tokens: &[&str]) -> bool {
+        self.push_label(tokens[0]);
+
+        self.line += 1;
+        let num_of_line = self.text.len();
+        let end = self.line >= num_of_line;
+        return end;
+    }
+
+    /// ラベルを追加して、以降の要素をそのラベルのノードに入れる
+    pub fn push_label(&mut self, first: &str) {
         let label = remove_colon(first);
+        let name = if first.starts_with(".") {
+            self.scope.clone() + &label
+        } else {
+            label.clone()
+        };
+        if let Err(message) = self.symbols.define_label(&name, self.location()) {
+            self.warn(&message);
+        }
+        if first.ends_with("::") {
+            self.symbols.export(&name);
+        }
+        let span = self.span();
         if first.starts_with(".") {
             // ローカルラベル
 
-            let name = remove_dash(&self.global.clone()) + "/" + &label;
+            let name = self.scope.clone() + "/" + &label;
             let current = self.get_current_label();
             let current_node = self.nodes.get_mut(&current).unwrap();
             current_node.next = name.clone();
             self.nodes.insert(
                 name.clone(),
                 Node {
-                    global: self.global.to_string(),
+                    global: self.scope.clone(),
                     text: name.clone() + "\n",
                     elements: Default::default(),
                     next: "".to_string(),
                     next_cond: "".to_string(),
                     calls: Default::default(),
+                    span: span.clone(),
+                    section: self.section.clone(),
                 },
             );
             self.local = label;
         } else {
             // グローバル
+            if self.section_nodes.contains(&label) {
+                self.rename_section_node(&label);
+            }
             let current = self.get_current_label();
             let current_node = self.nodes.get_mut(&current).unwrap();
             current_node.next = label.clone();
@@ -223,11 +540,28 @@ impl Parser {
                     next: "".to_string(),
                     next_cond: "".to_string(),
                     calls: Default::default(),
+                    span: span.clone(),
+                    section: self.section.clone(),
                 },
             );
             self.global = label.clone();
             self.local = "".to_string();
+            self.scope = label;
         }
+    }
+
+    /// 命令のパース
+    fn parse_opcode(&mut self, tokens: &[&str]) -> bool {
+        let text = self.text[self.line].to_string();
+        let mut op = parse_opcode_syntax(text, tokens);
+        if let Element::Opcode { expanded_from, .. } = &mut op {
+            let origin = self.origins[self.line];
+            *expanded_from = origin.invocation.map(|_| origin.line);
+        }
+        if let Some(message) = op.instruction().and_then(check_operand_count) {
+            self.warn(&message);
+        }
+        self.push_opcode(op);
 
         self.line += 1;
         let num_of_line = self.text.len();
@@ -235,130 +569,294 @@ impl Parser {
         return end;
     }
 
-    /// 命令のパース
-    fn parse_opcode(&mut self, tokens: &Vec<&str>) -> bool {
-        // TODO: JP, JR, CALLは特殊な処理が必要
-        let text = self.text[self.line].to_string();
-        let op = parse_opcode_syntax(text, tokens);
+    /// 命令を直接追加する
+    ///
+    /// `alternatives`はオペランドの飛び先のほかに辿れる飛び先で、callなら呼び出し先にも加える
+    pub fn push_instruction(
+        &mut self,
+        text: String,
+        op: &str,
+        operand1: &str,
+        operand2: &str,
+        alternatives: Vec<String>,
+    ) {
+        self.push_opcode(Element::Opcode {
+            text,
+            op: op.to_string(),
+            operand1: operand1.to_string(),
+            operand2: operand2.to_string(),
+            comment: "".to_string(),
+            expanded_from: None,
+            span: None,
+            alternatives,
+        });
+    }
 
-        match op {
-            Element::Opcode {
-                text,
-                op,
-                operand1,
-                operand2,
-                comment,
-            } if op == "call".to_string() => {
-                self.push_element(Element::Opcode {
-                    text,
-                    op,
-                    operand1: operand1.clone(),
-                    operand2: operand2.clone(),
-                    comment,
-                });
-                let callee = operand1;
-                let current_label = self.get_current_label();
-                let current_node = self.nodes.get_mut(&current_label).unwrap();
-                current_node.calls.push(callee);
+    /// 命令以外の行(データ定義など)を直接追加する
+    pub fn push_text(&mut self, text: String) {
+        self.push_element(Element::Text { text, span: None });
+    }
+
+    /// 構築したノードを取り出す
+    pub fn into_nodes(self) -> Nodes {
+        return self.nodes;
+    }
+
+    /// SECTIONの行から以降のラベルを置くSECTIONを切り替える
+    fn begin_section(&mut self, line: &str) {
+        let mut section = match parse_section(line, &self.symbols) {
+            Some(section) => section,
+            None => {
+                self.warn(&format!("invalid {}", token::SECTION));
+                return;
             }
-            Element::Opcode {
-                text,
-                op,
-                operand1,
-                operand2,
-                comment,
-            } if op == "jp".to_string() || op == "jr".to_string() || op == "jpba".to_string() => {
-                self.push_element(Element::Opcode {
-                    text,
-                    op,
-                    operand1: operand1.clone(),
-                    operand2: operand2.clone(),
-                    comment,
-                });
-                if operand1 == "c" || operand1 == "z" || operand1 == "nc" || operand1 == "nz" {
-                    let mut label = operand2;
-                    if !label.starts_with("@+$") {
-                        if label.starts_with(".") {
-                            label = remove_dash(&self.global.clone()) + "/" + &label;
-                        }
-                        let current_label = self.get_current_label();
-                        let current_node = self.nodes.get_mut(&current_label).unwrap();
-                        current_node.next = current_label.clone() + "'";
-                        current_node.next_cond = label.clone();
-                        self.nodes.insert(
-                            current_label.clone() + "'",
-                            Node {
-                                global: self.global.to_string(),
-                                text: label.clone() + "\n",
-                                elements: Default::default(),
-                                next: "".to_string(),
-                                next_cond: "".to_string(),
-                                calls: Default::default(),
-                            },
-                        );
-                        if self.local != "" {
-                            // ローカルスコープ内
-                            self.local += "'";
-                        } else {
-                            self.global += "'";
-                        }
-                    }
-                } else {
-                    let mut label = operand1;
-                    if !label.starts_with("@+$") {
-                        if label.starts_with(".") {
-                            label = remove_dash(&self.global.clone()) + "/" + &label;
-                        }
-                        let current_label = self.get_current_label();
-                        let current_node = self.nodes.get_mut(&current_label).unwrap();
-                        current_node.next = label.clone();
-                        self.nodes.insert(
-                            current_label.clone() + "'",
-                            Node {
-                                global: self.global.to_string(),
-                                text: label.clone() + "\n",
-                                elements: Default::default(),
-                                next: "".to_string(),
-                                next_cond: "".to_string(),
-                                calls: Default::default(),
-                            },
-                        );
-                        if self.local != "" {
-                            // ローカルスコープ内
-                            self.local += "'";
-                        } else {
-                            self.global += "'";
-                        }
-                    }
+        };
+        // SECTIONごとに配置が離れるので、前のラベルの続きにせず新しいノードを始める
+        let name = self.section_node_name(&section);
+        self.nodes.insert(
+            name.clone(),
+            Node {
+                global: name.clone(),
+                text: name.clone() + "\n",
+                elements: Default::default(),
+                next: "".to_string(),
+                next_cond: "".to_string(),
+                calls: Default::default(),
+                span: self.span(),
+                section: Some(section.name.clone()),
+            },
+        );
+        self.section_nodes.insert(name.clone());
+        self.global = name.clone();
+        self.local = "".to_string();
+        self.section = Some(section.name.clone());
+        // UNIONやFRAGMENTで同じ名前を繰り返しても最初の1つだけ残す
+        if !self.sections.iter().any(|s| s.name == section.name) {
+            section.file = self.file.clone();
+            section.label = name;
+            self.sections.push(section);
+        }
+    }
+
+    /// SECTIONの先頭のノード名(アドレスを指定していれば`ROM0_0040`、なければ`SECTION_Name`)
+    fn section_node_name(&self, section: &SectionDirective) -> String {
+        let base = match section.address {
+            Some(address) => format!("{}_{:04x}", section.kind, address),
+            None => {
+                let name: String = section
+                    .name
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                    .collect();
+                format!("SECTION_{}", name)
+            }
+        };
+        return self.free_node_name(&base);
+    }
+
+    /// `base`か、使われていなければ`base_2`、`base_3`…のうち空いているノード名
+    fn free_node_name(&self, base: &str) -> String {
+        let mut name = base.to_string();
+        let mut count = 1;
+        while self.nodes.contains_key(&name) || self.symbols.get(&name).is_some() {
+            count += 1;
+            name = format!("{}_{}", base, count);
+        }
+        return name;
+    }
+
+    /// ラベルと同じ名前になったSECTIONの先頭のノードを空いている名前に付け替える
+    ///
+    /// ジャンプの後に続くノード(`'`を付けた名前)もまとめて付け替える
+    fn rename_section_node(&mut self, name: &str) {
+        let renamed = self.free_node_name(name);
+        self.warn(&format!(
+            "label {} has the same name as the node of the SECTION, which is renamed to {}",
+            name, renamed
+        ));
+        let rename = |label: &str| -> Option<String> {
+            let suffix = label.strip_prefix(name)?;
+            if !suffix.chars().all(|c| c == '\'') {
+                return None;
+            }
+            return Some(renamed.clone() + suffix);
+        };
+
+        let keys: Vec<String> = self
+            .nodes
+            .keys()
+            .filter(|key| rename(key).is_some())
+            .cloned()
+            .collect();
+        for key in keys {
+            let mut node = self.nodes.remove(&key).unwrap();
+            if key == name {
+                node.text = renamed.clone() + &node.text[name.len()..];
+            }
+            self.nodes.insert(rename(&key).unwrap(), node);
+        }
+        for node in self.nodes.values_mut() {
+            for label in [&mut node.global, &mut node.next, &mut node.next_cond] {
+                if let Some(new) = rename(label) {
+                    *label = new;
                 }
             }
-            Element::Opcode {
-                text,
-                op,
-                operand1,
-                operand2,
-                comment,
-            } => {
-                self.push_element(Element::Opcode {
-                    text,
-                    op,
-                    operand1,
-                    operand2,
-                    comment,
-                });
+        }
+        if let Some(new) = rename(&self.global) {
+            self.global = new;
+        }
+        for section in &mut self.sections {
+            if section.label == name {
+                section.label = renamed.clone();
             }
-            _ => {}
         }
+        self.section_nodes.remove(name);
+        self.section_nodes.insert(renamed);
+    }
 
-        self.line += 1;
-        let num_of_line = self.text.len();
-        let end = self.line >= num_of_line;
-        return end;
+    /// 解析したSECTIONを取り出す
+    pub fn take_sections(&mut self) -> Vec<SectionDirective> {
+        return std::mem::take(&mut self.sections);
+    }
+
+    /// 前に解析したファイルで定義したシンボルを引き継ぐ
+    pub fn set_symbols(&mut self, symbols: SymbolTable) {
+        self.symbols = symbols;
+    }
+
+    /// 解析中に定義したシンボルを取り出す
+    pub fn take_symbols(&mut self) -> SymbolTable {
+        return std::mem::take(&mut self.symbols);
+    }
+
+    /// 解析中に見つかった問題を取り出す
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        return std::mem::take(&mut self.diagnostics);
+    }
+
+    /// 今の行の位置(ソースのない場合はNone)
+    ///
+    /// 桁は行の先頭の空白を飛ばした位置にする
+    fn span(&self) -> Option<Span> {
+        let origin = self.origins.get(self.line)?;
+        let column = match origin.invocation {
+            // 展開したマクロの行は呼び出した行を指す
+            Some(id) => self.invocations[id].column,
+            None => leading_columns(&self.text[self.line]),
+        };
+        return Some(Span {
+            file: self.file.clone(),
+            line: origin.line,
+            column,
+        });
+    }
+
+    fn location(&self) -> Location {
+        return Location {
+            file: self.file.clone(),
+            line: self.origins.get(self.line).map(|o| o.line),
+        };
+    }
+
+    fn push_opcode(&mut self, element: Element) {
+        let alternatives = element.alternatives().to_vec();
+        let (op, operand1, operand2) = match element.instruction() {
+            Some((op, operand1, operand2)) => (
+                op.to_ascii_uppercase(),
+                operand1.to_string(),
+                operand2.to_string(),
+            ),
+            None => {
+                self.push_element(element);
+                return;
+            }
+        };
+        self.push_element(element);
+
+        if opcode::is_call(&op) {
+            // call cc, labelの場合は第2オペランドが呼び出し先
+            let callee = if !operand2.is_empty() {
+                operand2
+            } else {
+                operand1
+            };
+            let current_label = self.get_current_label();
+            let current_node = self.nodes.get_mut(&current_label).unwrap();
+            current_node.calls.push(callee);
+            current_node.calls.extend(alternatives);
+        } else if opcode::is_jump(&op) {
+            if ["c", "z", "nc", "nz"]
+                .iter()
+                .any(|c| operand1.eq_ignore_ascii_case(c))
+            {
+                let mut label = operand2;
+                if !label.starts_with("@+$") {
+                    if label.starts_with(".") {
+                        label = self.scope.clone() + "/" + &label;
+                    }
+                    let current_label = self.get_current_label();
+                    let current_node = self.nodes.get_mut(&current_label).unwrap();
+                    current_node.next = current_label.clone() + "'";
+                    current_node.next_cond = label.clone();
+                    self.nodes.insert(
+                        current_label.clone() + "'",
+                        Node {
+                            global: self.current_global(),
+                            text: label.clone() + "\n",
+                            elements: Default::default(),
+                            next: "".to_string(),
+                            next_cond: "".to_string(),
+                            calls: Default::default(),
+                            span: None,
+                            section: self.section.clone(),
+                        },
+                    );
+                    if !self.local.is_empty() {
+                        // ローカルスコープ内
+                        self.local += "'";
+                    } else {
+                        self.global += "'";
+                    }
+                }
+            } else {
+                let mut label = operand1;
+                if !label.starts_with("@+$") {
+                    if label.starts_with(".") {
+                        label = self.scope.clone() + "/" + &label;
+                    }
+                    let current_label = self.get_current_label();
+                    let current_node = self.nodes.get_mut(&current_label).unwrap();
+                    current_node.next = label.clone();
+                    self.nodes.insert(
+                        current_label.clone() + "'",
+                        Node {
+                            global: self.current_global(),
+                            text: label.clone() + "\n",
+                            elements: Default::default(),
+                            next: "".to_string(),
+                            next_cond: "".to_string(),
+                            calls: Default::default(),
+                            span: None,
+                            section: self.section.clone(),
+                        },
+                    );
+                    if !self.local.is_empty() {
+                        // ローカルスコープ内
+                        self.local += "'";
+                    } else {
+                        self.global += "'";
+                    }
+                }
+            }
+        }
     }
 
     fn parse_text(&mut self) -> bool {
         let text = self.text[self.line].to_string();
-        self.push_element(Element::Text { text });
+        if let Err(message) = self.symbols.define_line(&text, self.location()) {
+            self.warn(&message);
+        }
+        self.push_element(Element::Text { text, span: None });
 
         self.line += 1;
         let num_of_line = self.text.len();
@@ -368,12 +866,8 @@ impl Parser {
 
     fn parse_include(
         &mut self,
-        tokens: &Vec<&str>,
-    ) -> (
-        bool,
-        Option<HashMap<String, Nodes>>,
-        Option<HashSet<String>>,
-    ) {
+        tokens: &[&str],
+    ) -> (bool, Option<HashMap<String, Nodes>>, Option<Macros>) {
         // includeに入れる
         let include = tokens[1]
             .trim_start_matches('"')
@@ -382,57 +876,80 @@ impl Parser {
         self.includes.push(include.clone());
 
         // includeファイルの絶対パスを取得
-        let abs_include = Path::new(&self.base_dir)
-            .join(include.clone())
-            .to_str()
-            .unwrap()
-            .to_string();
-        let mut p = Self::new(
-            abs_include.clone(),
-            self.base_dir.clone(),
-            self.macros.clone(),
-        );
-        let (result, macros) = p.parse(include);
+        let abs_include = self.base_dir.join(&include);
+        let parser = Self::new(&abs_include, &self.base_dir, self.macros.clone());
+        let (result, macros) = match parser {
+            Ok(mut p) => {
+                p.set_symbols(self.take_symbols());
+                // INCLUDEしたファイルは今のSECTIONの続きになる
+                p.section = self.section.clone();
+                p.sections = self.take_sections();
+                let (result, macros) = p.parse(include);
+                self.symbols = p.take_symbols();
+                self.section = p.section.clone();
+                self.sections = p.take_sections();
+                self.diagnostics.extend(p.take_diagnostics());
+                (Some(result), Some(macros))
+            }
+            Err(e) => {
+                // 読めないファイルは飛ばして解析を続ける
+                let e = match e {
+                    Error::Io { source, .. } => Error::MissingInclude {
+                        path: include,
+                        source,
+                    },
+                    e => e,
+                };
+                self.warn(&e.to_string());
+                (None, None)
+            }
+        };
 
         // 解析結果を詰める
         let text = self.text[self.line].to_string();
         self.push_element(Element::Include {
-            text: text,
-            target: abs_include,
+            text,
+            target: abs_include.to_string_lossy().to_string(),
+            span: None,
         });
 
         self.line += 1;
         let num_of_line = self.text.len();
         let end = self.line >= num_of_line;
-        return (end, Some(result), Some(macros));
+        return (end, result, macros);
     }
 
-    fn push_element(&mut self, element: Element) {
+    fn push_element(&mut self, mut element: Element) {
+        if element.span().is_none() {
+            element.set_span(self.span());
+        }
         let current = self.get_current_label();
         let node = self.nodes.get_mut(&current).unwrap();
-        (*node).text += match &element {
-            Element::Include { text, .. }
-            | Element::Opcode { text, .. }
-            | Element::Text { text, .. }
-            | Element::Macro { text, .. } => &text,
-        };
-        (*node).text += "\n";
-        (*node).elements.push(element);
+        node.text += element.text();
+        node.text += "\n";
+        node.elements.push(element);
     }
 
     fn get_current_label(&self) -> String {
         let current = match &self.local {
-            local if local != "" => remove_dash(&self.global.clone()) + "/" + &local,
+            local if !local.is_empty() => self.scope.clone() + "/" + local,
             _ => self.global.clone(),
         };
         return current;
     }
 
-    fn parse_macro(&mut self) -> bool {
-        let line = eat_space(&self.text[self.line]).to_string();
-        let tokens: Vec<&str> = line.split(|c| c == ' ' || c == '\t').collect();
-        let label = eat_space(tokens[0]).to_string();
-        let mut texts = vec![line];
+    /// 今のノードを持つグローバルラベル(ローカルスコープ内ならそのスコープ)
+    fn current_global(&self) -> String {
+        if self.local.is_empty() {
+            return self.global.clone();
+        }
+        return self.scope.clone();
+    }
+
+    fn parse_macro(&mut self, name: String) -> bool {
+        let start = self.line;
+        let span = self.span();
+        let mut texts = vec![eat_space(&self.text[self.line]).to_string()];
 
         loop {
             self.line += 1;
@@ -445,21 +962,332 @@ impl Parser {
             let line = eat_space(&self.text[self.line]).to_string();
             texts.push(line.clone());
 
-            let tokens: Vec<&str> = line.split(|c| c == ' ' || c == '\t').collect();
+            let tokens: Vec<&str> = line.split([' ', '\t']).collect();
             let first = eat_space(tokens[0]).to_string();
-            if first == token::ENDM {
+            if first.eq_ignore_ascii_case(token::ENDM) {
                 break;
             }
         }
 
+        let body = self.text[start + 1..self.line].to_vec();
+        let params = body
+            .iter()
+            .flat_map(|l| l.split('\\').skip(1))
+            .filter_map(|s| s.chars().next().and_then(|c| c.to_digit(10)))
+            .max()
+            .unwrap_or(0) as usize;
+        self.macros.insert(
+            name.clone(),
+            Macro {
+                name: name.clone(),
+                body,
+                params,
+                file: self.file.clone(),
+                line: self.origins[start].line,
+            },
+        );
         let text = texts.join("\n");
-        self.macros.insert(label[0..(label.len() - 1)].to_string()); // labelは:を取り除く
-        self.push_element(Element::Macro { label, texts, text });
+        let label = texts[0].split([' ', '\t']).next().unwrap_or("").to_string();
+        let label = if label.eq_ignore_ascii_case(token::MACRO) {
+            name
+        } else {
+            label
+        };
+        self.push_element(Element::Macro {
+            label,
+            texts,
+            text,
+            span,
+        });
         self.line += 1;
         let num_of_line = self.text.len();
         let end = self.line >= num_of_line;
         return end;
     }
+
+    /// マクロを呼び出す行の後ろにマクロの本体を展開する
+    ///
+    /// 引数などは展開した行を解析するときに`substitute`で置き換える
+    fn parse_macro_call(&mut self, name: &str) -> bool {
+        let origin = self.origins[self.line];
+        let depth = match origin.invocation {
+            Some(id) => self.invocations[id].depth + 1,
+            None => 0,
+        };
+        if depth > MAX_MACRO_DEPTH {
+            self.warn(&format!("macro {} is nested too deeply", name));
+            return self.parse_text();
+        }
+
+        let line = self.text[self.line].clone();
+        let args = line.trim_start()[name.len()..].to_string();
+        let args = split_args(expr::strip_comment(&args));
+        let column = match origin.invocation {
+            Some(id) => self.invocations[id].column,
+            None => leading_columns(&line),
+        };
+        self.unique += 1;
+        self.invocations.push(Invocation {
+            args,
+            shift: 0,
+            unique: self.unique,
+            depth,
+            column,
+        });
+        let id = self.invocations.len() - 1;
+        let body = self.macros[name].body.clone();
+        let at = self.line + 1;
+        let origins = vec![
+            Origin {
+                line: origin.line,
+                invocation: Some(id),
+            };
+            body.len()
+        ];
+        self.text.splice(at..at, body);
+        self.origins.splice(at..at, origins);
+        return self.parse_text();
+    }
+
+    /// REPTやFORからENDRまでを繰り返した行に置き換える
+    ///
+    /// 繰り返すごとに`\@`を置き換え、FORの変数は値に(`{V}`は`$`付きの16進数、`{d:V}`は10進数に)置き換える
+    fn parse_repeat(&mut self, directive: &str, line: &str) -> bool {
+        // 対応するENDRを探す
+        let mut depth = 0;
+        let mut end = None;
+        for i in self.line + 1..self.text.len() {
+            let first = eat_space(&self.text[i])
+                .split([' ', '\t'])
+                .next()
+                .unwrap_or("")
+                .to_ascii_uppercase();
+            if first == token::REPT || first == token::FOR {
+                depth += 1;
+            } else if first == token::ENDR {
+                if depth == 0 {
+                    end = Some(i);
+                    break;
+                }
+                depth -= 1;
+            }
+        }
+        let end = match end {
+            Some(end) => end,
+            None => {
+                self.warn(&format!("{} without {}", directive, token::ENDR));
+                return self.parse_text();
+            }
+        };
+
+        let args = expr::strip_comment(line)[directive.len()..].to_string();
+        let iterations = match self.repeat_values(directive, &args) {
+            Ok(iterations) => iterations,
+            Err(e) => {
+                self.warn(&format!("cannot evaluate {}: {}", directive, e));
+                vec![]
+            }
+        };
+
+        let body: Vec<(String, Origin)> = (self.line + 1..end)
+            .map(|i| (self.text[i].clone(), self.origins[i]))
+            .collect();
+        let mut text = vec![];
+        let mut origins = vec![];
+        for value in iterations {
+            self.unique += 1;
+            let unique = format!("_u{}", self.unique);
+            for (line, origin) in &body {
+                let mut line = line.replace("\\@", &unique);
+                if let Some((name, value)) = &value {
+                    // {N}は32bitの値として16進数にする
+                    line = line.replace(&format!("{{{}}}", name), &format!("${:X}", *value as u32));
+                    line = line.replace(&format!("{{d:{}}}", name), &value.to_string());
+                    line = replace_word(&line, name, &value.to_string());
+                }
+                text.push(line);
+                origins.push(*origin);
+            }
+        }
+        self.text.splice(self.line..=end, text);
+        self.origins.splice(self.line..=end, origins);
+        return self.line >= self.text.len();
+    }
+
+    /// 繰り返しごとの(FORの変数, 値)
+    fn repeat_values(
+        &mut self,
+        directive: &str,
+        args: &str,
+    ) -> Result<Vec<Option<(String, i64)>>, String> {
+        let int = |s: &str| expr::eval_str(s, &self.symbols).and_then(|v| v.as_int());
+        if directive == token::REPT {
+            let count = int(args)?;
+            if count > MAX_REPEAT {
+                self.warn(&format!(
+                    "{} count {} is too large, repeating only {} times",
+                    directive, count, MAX_REPEAT
+                ));
+            }
+            return Ok((0..count.min(MAX_REPEAT)).map(|_| None).collect());
+        }
+
+        // FOR V, stop / FOR V, start, stop / FOR V, start, stop, step
+        let args = split_args(args);
+        let (start, stop, step) = match args.len() {
+            2 => (0, int(&args[1])?, 1),
+            3 => (int(&args[1])?, int(&args[2])?, 1),
+            4 => (int(&args[1])?, int(&args[2])?, int(&args[3])?),
+            _ => return Err("FOR takes 2 to 4 arguments".to_string()),
+        };
+        if step == 0 {
+            return Err("step must not be 0".to_string());
+        }
+        let name = args[0].clone();
+        let mut values = vec![];
+        let mut value = start;
+        while (step > 0 && value < stop) || (step < 0 && value > stop) {
+            if values.len() as i64 >= MAX_REPEAT {
+                self.warn(&format!(
+                    "{} {} repeats too many times, repeating only {} times",
+                    directive, name, MAX_REPEAT
+                ));
+                break;
+            }
+            values.push(Some((name.clone(), value)));
+            value += step;
+        }
+        // ループの後の変数の値は残る(定義できなくても繰り返しは展開する)
+        let location = self.location();
+        if let Err(message) = self
+            .symbols
+            .define_line(&format!("{} = {}", name, value), location)
+        {
+            self.warn(&message);
+        }
+        return Ok(values);
+    }
+
+    /// IF/ELIF/ELSE/ENDCの行と、成立していない分岐の中の行ならtrueを返す
+    ///
+    /// 条件はその行までに定義したシンボルで評価する
+    fn parse_condition(&mut self, first: &str, line: &str) -> bool {
+        let outer = self.conditions.last().is_none_or(|c| c.active);
+        let directive = first.to_ascii_uppercase();
+        match &directive[..] {
+            token::IF => {
+                // 外側が成立していなければ条件は評価しない
+                let active = outer && self.evaluate_condition(line);
+                self.conditions.push(Condition {
+                    parent: outer,
+                    active,
+                    taken: active,
+                });
+            }
+            token::ELIF | token::ELSE | token::ENDC if self.conditions.is_empty() => {
+                self.warn(&format!("{} without {}", directive, token::IF));
+            }
+            token::ELIF => {
+                let c = *self.conditions.last().unwrap();
+                let active = c.parent && !c.taken && self.evaluate_condition(line);
+                let c = self.conditions.last_mut().unwrap();
+                c.active = active;
+                c.taken |= active;
+            }
+            token::ELSE => {
+                let c = self.conditions.last_mut().unwrap();
+                c.active = c.parent && !c.taken;
+                c.taken = true;
+            }
+            token::ENDC => {
+                self.conditions.pop();
+            }
+            _ => return !outer,
+        }
+        return true;
+    }
+
+    /// `IF expr`の条件を評価する(評価できなければ成立しないものとする)
+    fn evaluate_condition(&mut self, line: &str) -> bool {
+        let expression = expr::strip_comment(line).trim();
+        let expression = expression
+            .find(char::is_whitespace)
+            .map_or("", |i| &expression[i..]);
+        match expr::eval_str(expression, &self.symbols).and_then(|v| v.as_int()) {
+            Ok(value) => return value != 0,
+            Err(e) => {
+                self.warn(&format!("cannot evaluate condition: {}", e));
+                return false;
+            }
+        }
+    }
+
+    /// 今の行についての警告を加える
+    fn warn(&mut self, message: &str) {
+        let diagnostic = match self.span() {
+            Some(span) => Diagnostic::warning(message).at_span(&span),
+            None => {
+                let location = self.location();
+                Diagnostic::warning(message).at(&location.file, location.line)
+            }
+        };
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// 展開したマクロの行の`\1`〜`\9`、`\<n>`、`\#`、`\@`、`_NARG`を置き換える
+    ///
+    fn substitute(&mut self, id: usize) {
+        let invocation = &self.invocations[id];
+        let args = &invocation.args[invocation.shift.min(invocation.args.len())..];
+        let arg = |n: usize| -> String {
+            match n {
+                0 => "".to_string(),
+                n => args.get(n - 1).cloned().unwrap_or_default(),
+            }
+        };
+
+        let chars: Vec<char> = self.text[self.line].chars().collect();
+        let mut line = "".to_string();
+        let mut i = 0;
+        while i < chars.len() {
+            if chars[i] != '\\' || i + 1 >= chars.len() {
+                line.push(chars[i]);
+                i += 1;
+                continue;
+            }
+            let c = chars[i + 1];
+            i += 2;
+            match c {
+                '1'..='9' => line += &arg(c.to_digit(10).unwrap() as usize),
+                '@' => line += &format!("_u{}", invocation.unique),
+                '#' => line += &args.join(", "),
+                '<' => {
+                    let end = chars[i..].iter().position(|&c| c == '>');
+                    match end {
+                        Some(end) => {
+                            let inner: String = chars[i..i + end].iter().collect();
+                            let n = match inner.parse::<usize>() {
+                                Ok(n) => Some(n),
+                                Err(_) => expr::eval_str(&inner, &self.symbols)
+                                    .and_then(|v| v.as_int())
+                                    .ok()
+                                    .map(|n| n as usize),
+                            };
+                            line += &n.map(arg).unwrap_or_default();
+                            i += end + 1;
+                        }
+                        None => line += "\\<",
+                    }
+                }
+                c => {
+                    line.push('\\');
+                    line.push(c);
+                }
+            }
+        }
+        let line = replace_word(&line, "_NARG", &args.len().to_string());
+        self.text[self.line] = line;
+    }
 }
 
 pub fn get_node<'a>(nodes: &'a Nodes, label: &str) -> Option<&'a Node> {
@@ -491,7 +1319,7 @@ fn eat_start_space(s: &str) -> &str {
     return s.trim_start().trim_start_matches('\t');
 }
 
-fn parse_opcode_syntax(text: String, tokens: &Vec<&str>) -> Element {
+fn parse_opcode_syntax(text: String, tokens: &[&str]) -> Element {
     let mut op = "".to_string();
     let mut operand1 = "".to_string();
     let mut operand2 = "".to_string();
@@ -515,12 +1343,12 @@ fn parse_opcode_syntax(text: String, tokens: &Vec<&str>) -> Element {
             }
 
             if !comment_exist {
-                operand1 = tokens[1..].join(" ");
+                operand1 = eat_space(&tokens[1..].join(" ")).to_string();
             }
         }
     }
 
-    if operand1 != "" {
+    if !operand1.is_empty() {
         let tmp = operand1.clone();
         let operands: Vec<&str> = tmp.split(',').collect();
         if operands.len() == 2 {
@@ -535,13 +1363,537 @@ fn parse_opcode_syntax(text: String, tokens: &Vec<&str>) -> Element {
         operand1,
         operand2,
         comment,
+        expanded_from: None,
+        span: None,
+        alternatives: vec![],
     };
 }
 
+/// 命令のオペランドの数が正しくなければ警告のメッセージを返す
+///
+/// `callba`などのマクロは調べない
+fn check_operand_count((op, operand1, operand2): (&str, &str, &str)) -> Option<String> {
+    let (min, max) = operand_range(&op.to_ascii_uppercase())?;
+    let count = match (operand1, operand2) {
+        ("", _) => 0,
+        (_, "") => split_args(operand1).len(),
+        _ => 2,
+    };
+    if min <= count && count <= max {
+        return None;
+    }
+    let expected = if min == max {
+        min.to_string()
+    } else {
+        format!("{} or {}", min, max)
+    };
+    return Some(format!(
+        "{} takes {} operand(s) but {} given",
+        op, expected, count
+    ));
+}
+
+/// 命令が取れるオペランドの数の範囲
+fn operand_range(op: &str) -> Option<(usize, usize)> {
+    use opcode::*;
+    let range = match op {
+        CCF | CPL | DAA | DI | EI | HALT | NOP | SCF | RLA | RLCA | RRA | RRCA | RETI => (0, 0),
+        RET | STOP => (0, 1),
+        INC | DEC | PUSH | POP | SWAP | RL | RLC | RR | RRC | SLA | SRA | SRL | RST => (1, 1),
+        ADD | ADC | SBC | SUB | AND | OR | XOR | CP | JP | JR | CALL => (1, 2),
+        LD | LDH | LDI | LDD | BIT | RES | SET => (2, 2),
+        _ => return None,
+    };
+    return Some(range);
+}
+
+/// 字下げして書ける命令以外の語か
+fn is_known_directive(first: &str) -> bool {
+    let upper = first.to_ascii_uppercase();
+    return token::DIRECTIVE_LIST.contains(&&upper[..])
+        || opcode::DEFINE_LIST.contains(&&upper[..]);
+}
+
+/// `SECTION "name", ROM0[$100], BANK[1]`をパースする(SECTIONの行でなければNone)
+///
+/// アドレスやバンクの式は`env`のシンボルで評価する
+pub fn parse_section(text: &str, env: &dyn Env) -> Option<SectionDirective> {
+    let line = eat_space(expr::strip_comment(text));
+    let keyword = line.get(..token::SECTION.len())?;
+    if !keyword.eq_ignore_ascii_case(token::SECTION) {
+        return None;
+    }
+    let mut args = split_args(&line[token::SECTION.len()..]).into_iter();
+    // `SECTION UNION "name"`や`SECTION FRAGMENT "name"`
+    let name = args.next()?;
+    let quote = name.find('"')?;
+    let modifier = match name[..quote].trim().to_ascii_uppercase().as_str() {
+        "UNION" => SectionModifier::Union,
+        "FRAGMENT" => SectionModifier::Fragment,
+        _ => SectionModifier::Normal,
+    };
+    let name = name[quote..].trim_matches('"').to_string();
+    let eval = |e: &str| expr::eval_str(e, env).and_then(|v| v.as_int()).ok();
+    let (kind, address) = split_bracket(&args.next()?);
+    let mut section = SectionDirective {
+        name,
+        kind: kind.to_ascii_uppercase(),
+        address: address.and_then(|a| eval(&a)).map(|a| a as u16),
+        bank: None,
+        align: None,
+        align_offset: None,
+        modifier,
+        file: "".to_string(),
+        label: "".to_string(),
+    };
+    for option in args {
+        let (option, value) = split_bracket(&option);
+        let value = value.unwrap_or_default();
+        if option.eq_ignore_ascii_case("BANK") {
+            section.bank = eval(&value).map(|b| b as usize);
+        } else if option.eq_ignore_ascii_case("ALIGN") {
+            let mut values = split_args(&value).into_iter();
+            section.align = values.next().and_then(|a| eval(&a)).map(|a| a as u8);
+            section.align_offset = values.next().and_then(|o| eval(&o)).map(|o| o as u16);
+        }
+    }
+    return Some(section);
+}
+
+/// `ROM0[$100]`を`ROM0`と`$100`に分ける
+fn split_bracket(s: &str) -> (String, Option<String>) {
+    match (s.find('['), s.rfind(']')) {
+        (Some(open), Some(close)) if open < close => {
+            return (
+                s[..open].trim().to_string(),
+                Some(s[open + 1..close].trim().to_string()),
+            );
+        }
+        _ => return (s.trim().to_string(), None),
+    }
+}
+
+/// 行頭の空白を飛ばした桁(1始まり)
+fn leading_columns(text: &str) -> usize {
+    return text.chars().take_while(|c| c.is_whitespace()).count() + 1;
+}
+
+/// マクロの引数をカンマで分ける(文字列と括弧、`[]`の中のカンマでは分けない)
+fn split_args(s: &str) -> Vec<String> {
+    let s = s.trim();
+    if s.is_empty() {
+        return vec![];
+    }
+    let mut args = vec![];
+    let mut arg = "".to_string();
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '(' | '[' if !quoted => depth += 1,
+            ')' | ']' if !quoted => depth -= 1,
+            '\\' => {
+                if let Some(next) = chars.next() {
+                    if next != ',' {
+                        arg.push(c);
+                    }
+                    arg.push(next);
+                }
+                continue;
+            }
+            ',' if !quoted && depth == 0 => {
+                args.push(arg.trim().to_string());
+                arg = "".to_string();
+                continue;
+            }
+            _ => {}
+        }
+        arg.push(c);
+    }
+    args.push(arg.trim().to_string());
+    return args;
+}
+
+/// 識別子として現れる`word`を置き換える(文字列リテラルの中は置き換えない)
+fn replace_word(s: &str, word: &str, to: &str) -> String {
+    let mut result = "".to_string();
+    let mut rest = s;
+    while let Some(start) = rest.find('"') {
+        result += &replace_identifier(&rest[..start], word, to);
+        // 閉じる"までそのまま残す(\"は文字列の中の")
+        let mut end = rest.len();
+        let mut escaped = false;
+        for (i, c) in rest[start + 1..].char_indices() {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => {
+                    end = start + 1 + i + 1;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        result += &rest[start..end];
+        rest = &rest[end..];
+    }
+    return result + &replace_identifier(rest, word, to);
+}
+
+/// 文字列リテラルを含まない`s`で、識別子として現れる`word`を置き換える
+fn replace_identifier(s: &str, word: &str, to: &str) -> String {
+    let is_ident = |c: char| c.is_alphanumeric() || "_.@#".contains(c);
+    let mut result = "".to_string();
+    let mut rest = s;
+    while let Some(i) = rest.find(word) {
+        let before = rest[..i].chars().last();
+        let after = rest[i + word.len()..].chars().next();
+        result += &rest[..i];
+        if before.map(is_ident) != Some(true) && after.map(is_ident) != Some(true) {
+            result += to;
+        } else {
+            result += word;
+        }
+        rest = &rest[i + word.len()..];
+    }
+    return result + rest;
+}
+
+/// `NAME RB 1`のようなRSカウンタを使った定義か
+fn is_rs_definition(tokens: &[&str]) -> bool {
+    return tokens.len() > 1
+        && [token::RB, token::RW, token::RL].contains(&&tokens[1].to_ascii_uppercase()[..]);
+}
+
 fn remove_colon(src: &str) -> String {
     return src.trim_end_matches(":").to_string();
 }
 
-fn remove_dash(src: &str) -> String {
+pub fn remove_dash(src: &str) -> String {
     return src.trim_end_matches("'").to_string();
 }
+
+/// テスト用にソースの断片を`main.asm`として解析する
+///
+/// 解析した後のシンボルや診断は返した`Parser`から取り出す
+#[cfg(test)]
+pub fn parse_snippet(text: &str) -> (HashMap<String, Nodes>, Macros, Parser) {
+    return parse_snippet_with(text, SymbolTable::default());
+}
+
+/// `symbols`を定義した状態でソースの断片を解析する
+#[cfg(test)]
+pub fn parse_snippet_with(
+    text: &str,
+    symbols: SymbolTable,
+) -> (HashMap<String, Nodes>, Macros, Parser) {
+    let mut p = Parser::with_text(
+        text.lines().map(|l| l.to_string()).collect(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+    );
+    p.set_symbols(symbols);
+    let (result, macros) = p.parse("main.asm".to_string());
+    return (result, macros, p);
+}
+
+#[test]
+fn test_expand_macro() {
+    let text = "MACRO wait_for
+.wait\\@
+\tld a, [\\1]
+\tcp \\2
+\tjr nz, .wait\\@
+ENDM
+far: MACRO
+\tld a, _NARG
+\tSHIFT
+\tcall \\1
+ENDM
+start:
+\twait_for rLY, 144
+\tfar ignored, sub
+\tret
+sub:
+\tret
+";
+    let (result, macros, _) = parse_snippet(text);
+    assert_eq!(macros["wait_for"].params, 2);
+    assert_eq!(macros["far"].body.len(), 3);
+
+    let nodes = &result["main.asm"];
+    let wait = &nodes["start/.wait_u1"];
+    assert_eq!(wait.next_cond, "start/.wait_u1");
+    match &wait.elements[0] {
+        Element::Opcode {
+            text,
+            expanded_from,
+            ..
+        } => {
+            assert_eq!(text, "\tld a, [rLY]");
+            assert_eq!(*expanded_from, Some(13));
+        }
+        _ => panic!("not an instruction"),
+    }
+    let after = &nodes["start/.wait_u1'"];
+    assert_eq!(after.calls, vec!["sub"]);
+    assert_eq!(after.elements[0].text(), "\tfar ignored, sub");
+    assert_eq!(after.elements[1].instruction(), Some(("ld", "a", "2")));
+}
+
+#[test]
+fn test_far_call_macro() {
+    let text = "farcall: MACRO
+\tld a, BANK(\\1)
+\tld [$2000], a
+\tcall \\1
+ENDM
+start:
+\tfarcall sub
+\tcallba sub
+\tret
+sub:
+\tret
+";
+    let (result, _, mut p) = parse_snippet(text);
+    let start = &result["main.asm"]["start"];
+    // プロジェクトで定義したfarcallは展開し、定義のないcallbaはcallとして辿る
+    assert_eq!(start.calls, vec!["sub", "sub"]);
+    assert_eq!(
+        start.elements[1].instruction(),
+        Some(("ld", "a", "BANK(sub)"))
+    );
+    assert_eq!(start.elements[4].instruction(), Some(("callba", "sub", "")));
+    assert!(p.take_diagnostics().is_empty());
+}
+
+#[test]
+fn test_conditional_assembly() {
+    let text = "VERSION EQU 2
+start:
+IF !DEF(VERSION)
+\tcall none
+ELIF VERSION == 2
+\tIF DEF(DEBUG)
+\t\tcall debug
+\tELSE
+\t\tcall release
+\tENDC
+ELSE
+\tcall old
+ENDC
+\tret
+";
+    let (result, _, mut p) = parse_snippet(text);
+    assert_eq!(result["main.asm"]["start"].calls, vec!["release"]);
+    assert!(p.take_diagnostics().is_empty());
+
+    // -D DEBUGで定義した場合
+    let mut symbols = SymbolTable::default();
+    let location = Location {
+        file: "<command line>".to_string(),
+        line: None,
+    };
+    symbols.define_string("DEBUG", "1", location).unwrap();
+    let (result, _, _) = parse_snippet_with(text, symbols);
+    assert_eq!(result["main.asm"]["start"].calls, vec!["debug"]);
+}
+
+#[test]
+fn test_unroll_repeat() {
+    let text = "start:
+REPT 2
+.skip\\@
+\tjr z, .skip\\@
+ENDR
+FOR N, 1, 4
+\tcall sub{d:N}
+\tld a, N * 2
+ENDR
+FOR V, -1, 0
+\tdb \"V\", {V}, V
+ENDR
+REPT 100000
+ENDR
+\tret
+";
+    let (result, _, mut p) = parse_snippet(text);
+    let nodes = &result["main.asm"];
+    // 文字列の中の変数は置き換えず、{V}は32bitの16進数にする
+    let last = &nodes["start/.skip_u2'"];
+    assert_eq!(last.elements[6].text(), "\tdb \"V\", $FFFFFFFF, -1");
+    let diagnostics = p.take_diagnostics();
+    assert_eq!(
+        diagnostics[0].message,
+        "REPT count 100000 is too large, repeating only 65536 times"
+    );
+    assert_eq!(nodes["start/.skip_u1"].next_cond, "start/.skip_u1");
+    assert_eq!(nodes["start/.skip_u2"].next_cond, "start/.skip_u2");
+    let last = &nodes["start/.skip_u2'"];
+    assert_eq!(last.calls, vec!["sub1", "sub2", "sub3"]);
+    assert_eq!(last.elements[5].instruction(), Some(("ld", "a", "3 * 2")));
+    assert_eq!(p.symbols.get("N").unwrap().value, Some(expr::Value::Int(4)));
+}
+
+#[test]
+fn test_unroll_for_constant() {
+    let text = "N EQU 7
+start:
+FOR N, 2
+\tcall sub{d:N}
+ENDR
+\tret
+";
+    let (result, _, mut p) = parse_snippet(text);
+    // 変数を定義できなくても繰り返しは展開する
+    assert_eq!(result["main.asm"]["start"].calls, vec!["sub0", "sub1"]);
+    let diagnostics = p.take_diagnostics();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].message, "N is already defined at main.asm:1");
+    assert_eq!(diagnostics[0].line, Some(3));
+    assert_eq!(p.symbols.get("N").unwrap().value, Some(expr::Value::Int(7)));
+}
+
+#[test]
+fn test_diagnostics() {
+    let text = "INCLUDE \"missing.inc\"
+start:
+\tld a
+\tfoo a, b
+\tret
+";
+    let (result, _, mut p) = parse_snippet(text);
+    let diagnostics = p.take_diagnostics();
+    let messages: Vec<&str> = diagnostics.iter().map(|d| &d.message[..]).collect();
+    assert!(messages[0].starts_with("cannot open include file missing.inc"));
+    assert_eq!(messages[1], "ld takes 2 operand(s) but 1 given");
+    assert_eq!(messages[2], "unknown mnemonic foo");
+    assert_eq!(
+        (diagnostics[2].line, diagnostics[2].column),
+        (Some(4), Some(2))
+    );
+
+    // 解析は続けて、要素には位置を残す
+    let start = &result["main.asm"]["start"];
+    assert_eq!(start.span.as_ref().unwrap().line, 2);
+    let span = start.elements[2].span().unwrap();
+    assert_eq!((&span.file[..], span.line, span.column), ("main.asm", 5, 2));
+}
+
+#[test]
+fn test_parse_section() {
+    let symbols = SymbolTable::default();
+    assert_eq!(
+        parse_section(
+            "\tSECTION\t\"Start\",ROM0[$100]\t\t; start vector",
+            &symbols
+        ),
+        Some(SectionDirective {
+            name: "Start".to_string(),
+            kind: "ROM0".to_string(),
+            address: Some(0x100),
+            bank: None,
+            align: None,
+            align_offset: None,
+            modifier: SectionModifier::Normal,
+            file: "".to_string(),
+            label: "".to_string(),
+        })
+    );
+    let section = parse_section("SECTION FRAGMENT \"Code\", romx, BANK[2]", &symbols).unwrap();
+    assert_eq!(
+        (&section.name[..], &section.kind[..], section.bank),
+        ("Code", "ROMX", Some(2))
+    );
+    assert_eq!(section.address, None);
+    assert_eq!(section.modifier, SectionModifier::Fragment);
+    let section = parse_section("section \"Buffer\", WRAMX, ALIGN[8, 2]", &symbols).unwrap();
+    assert_eq!((section.align, section.align_offset), (Some(8), Some(2)));
+    assert_eq!(section.bank(), None);
+    assert_eq!(parse_section("\tld a, b", &symbols), None);
+
+    let text = "\tEXPORT Init, Update
+SECTION \"Code\", ROMX, BANK[1]
+Init::
+\tret
+Update:
+\tret
+ENDSECTION
+Draw::
+\tret
+";
+    let (result, _, mut p) = parse_snippet(text);
+    let nodes = &result["main.asm"];
+    assert_eq!(nodes["Init"].section, Some("Code".to_string()));
+    assert_eq!(nodes["Draw"].section, None);
+    // SECTIONから新しいノードを始め、前のノードから流れ込まない
+    assert_eq!(nodes["SECTION_Code"].next, "Init");
+    assert_eq!(nodes["main"].next, "");
+    let sections = p.take_sections();
+    assert_eq!(sections.len(), 1);
+    assert_eq!(
+        (
+            &sections[0].file[..],
+            &sections[0].label[..],
+            sections[0].bank()
+        ),
+        ("main.asm", "SECTION_Code", Some(1))
+    );
+    let symbols = p.take_symbols();
+    let exported: Vec<&str> = symbols
+        .exported_labels()
+        .iter()
+        .map(|s| &s.name[..])
+        .collect();
+    assert_eq!(exported, ["Draw", "Init", "Update"]);
+}
+
+#[test]
+fn test_section_node_collision() {
+    let text = "SECTION \"Vector\", ROM0[$38]
+\tcp 1
+\tjr z, Handler
+\tret
+ROM0_0038:
+\tret
+Handler:
+\tret
+";
+    let (result, _, mut p) = parse_snippet(text);
+    let nodes = &result["main.asm"];
+    // 同じ名前のラベルはSECTIONのノードを上書きせず、SECTIONのノードを付け替える
+    assert_eq!(nodes["ROM0_0038"].global, "ROM0_0038");
+    assert_eq!(nodes["ROM0_0038"].elements.len(), 1);
+    let section = &nodes["ROM0_0038_2"];
+    assert!(section.text.starts_with("ROM0_0038_2\n"));
+    assert_eq!(section.next, "ROM0_0038_2'");
+    assert_eq!(section.next_cond, "Handler");
+    assert_eq!(nodes["ROM0_0038_2'"].global, "ROM0_0038_2");
+    assert_eq!(nodes["ROM0_0038_2'"].next, "ROM0_0038");
+    assert_eq!(p.take_sections()[0].label, "ROM0_0038_2");
+    let diagnostics = p.take_diagnostics();
+    assert_eq!(
+        diagnostics[0].message,
+        "label ROM0_0038 has the same name as the node of the SECTION, which is renamed to ROM0_0038_2"
+    );
+}
+
+#[test]
+fn test_section_local_scope() {
+    let text = "Main:
+\tret
+SECTION \"Code\", ROM0
+.loop
+\tjr .loop
+";
+    let (result, _, mut p) = parse_snippet(text);
+    let nodes = &result["main.asm"];
+    // SECTIONのノードではなく、前のグローバルラベルのローカルラベルになる
+    assert_eq!(nodes["SECTION_Code"].next, "Main/.loop");
+    assert_eq!(nodes["Main/.loop"].global, "Main");
+    assert_eq!(nodes["Main/.loop"].next, "Main/.loop");
+    assert!(p.take_symbols().get("Main.loop").is_some());
+}
@@ -0,0 +1,365 @@
+//! 入力ファイルと開始ラベルを指定して解析する入口
+//!
+//! ```no_run
+//! use flowchartgb::Project;
+//!
+//! let project = Project::from_rom("examples/picture/picture.gb").analyze()?;
+//! for entry in project.entries() {
+//!     println!("{}", entry);
+//! }
+//! # Ok::<(), flowchartgb::Error>(())
+//! ```
+
+use super::analyzer::Analyzer;
+use super::callgraph::CallGraph;
+use super::cfg::Cfg;
+use super::diagnostic::Diagnostic;
+use super::error::{Error, Result};
+use super::model::Analysis;
+use super::symbol::SymbolTable;
+use std::path::{Path, PathBuf};
+
+/// 図の出力形式
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    Flowchart,
+    Json,
+    Dot,
+    Mermaid,
+    Html,
+    Svg,
+}
+
+/// 出力するグラフ
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Graph {
+    /// 開始ラベルからの制御フロー
+    Cfg,
+    /// ルーチン間の呼び出し関係
+    Calls,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Source {
+    Asm,
+    Rom,
+}
+
+/// 解析する入力の設定
+#[derive(Debug, Clone)]
+pub struct Project {
+    path: PathBuf,
+    source: Source,
+    entries: Vec<String>,
+    defines: Vec<(String, String)>,
+    output_dir: Option<PathBuf>,
+    workspace: Option<PathBuf>,
+    split: bool,
+    all_exported: bool,
+    per_entry: bool,
+}
+
+impl Project {
+    /// RGBASMのソースファイルを解析する
+    pub fn from_asm<P: AsRef<Path>>(path: P) -> Self {
+        return Self::new(path.as_ref(), Source::Asm);
+    }
+
+    /// ROMファイル(gb/gbc)を逆アセンブルして解析する
+    pub fn from_rom<P: AsRef<Path>>(path: P) -> Self {
+        return Self::new(path.as_ref(), Source::Rom);
+    }
+
+    fn new(path: &Path, source: Source) -> Self {
+        return Self {
+            path: path.to_path_buf(),
+            source,
+            entries: vec![],
+            defines: vec![],
+            output_dir: None,
+            workspace: None,
+            split: false,
+            all_exported: false,
+            per_entry: false,
+        };
+    }
+
+    /// 開始ラベルを加える
+    ///
+    /// 1つも指定しなければ、ROMでは見つかったエントリポイント全てから、
+    /// asmでは`Analyzer::discover_entries`で探したラベルから辿る
+    pub fn entry(mut self, label: &str) -> Self {
+        self.entries.push(label.to_string());
+        return self;
+    }
+
+    /// `::`やEXPORTで公開したラベル全てを開始ラベルに加える(asmのみ)
+    pub fn entry_all_exported(mut self, all_exported: bool) -> Self {
+        self.all_exported = all_exported;
+        return self;
+    }
+
+    /// 開始ラベルごとに`<output_dir>/<label>/`へ分けて出力する
+    pub fn per_entry(mut self, per_entry: bool) -> Self {
+        self.per_entry = per_entry;
+        return self;
+    }
+
+    /// `rgbasm -D NAME=value`と同じように文字列のシンボルを定義する(asmのみ)
+    pub fn define(mut self, name: &str, value: &str) -> Self {
+        self.defines.push((name.to_string(), value.to_string()));
+        return self;
+    }
+
+    /// 図を出力するディレクトリ(なければ作る)
+    ///
+    /// 指定しなければ入力ファイルと同じディレクトリに出力する
+    pub fn output_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.output_dir = Some(dir.as_ref().to_path_buf());
+        return self;
+    }
+
+    /// flowchart形式の図をルーチンごとのファイルに分けて出力する
+    ///
+    /// `<output_dir>/<name>/`の下にソースと同じ構成で書き、一覧を`index.md`に書く
+    pub fn split(mut self, split: bool) -> Self {
+        self.split = split;
+        return self;
+    }
+
+    /// ROMを逆アセンブルしたソースを書き出すディレクトリ(romのみ)
+    ///
+    /// 指定しなければ一時ディレクトリに書き出し、`Analyzed`を捨てるときに消す
+    pub fn workspace<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.workspace = Some(dir.as_ref().to_path_buf());
+        return self;
+    }
+
+    /// 入力を解析する
+    ///
+    /// 解析を続けられる問題は`Analyzed::diagnostics`に集める
+    pub fn analyze(self) -> Result<Analyzed> {
+        let mut analyzer = Analyzer::new();
+        let output_dir = match self.output_dir {
+            Some(dir) => dir,
+            None => default_output_dir(&self.path),
+        };
+        let entries = match self.source {
+            Source::Rom => {
+                if let Some(dir) = self.workspace {
+                    analyzer.set_workspace(dir);
+                }
+                analyzer.analyze_rom(&self.path)?;
+                if self.entries.is_empty() {
+                    analyzer.entries().to_vec()
+                } else {
+                    self.entries
+                }
+            }
+            Source::Asm => {
+                for (name, value) in &self.defines {
+                    analyzer.define(name, value)?;
+                }
+                analyzer.init_analyze(vec![self.path])?;
+                let mut entries = self.entries;
+                if self.all_exported {
+                    for label in analyzer.exported_labels() {
+                        if !entries.contains(&label) {
+                            entries.push(label);
+                        }
+                    }
+                }
+                if entries.is_empty() {
+                    entries = analyzer.discover_entries();
+                }
+                entries
+            }
+        };
+        return Ok(Analyzed {
+            analyzer,
+            entries,
+            output_dir,
+            split: self.split,
+            per_entry: self.per_entry,
+        });
+    }
+}
+
+/// 解析した結果
+pub struct Analyzed {
+    analyzer: Analyzer,
+    entries: Vec<String>,
+    output_dir: PathBuf,
+    split: bool,
+    per_entry: bool,
+}
+
+impl Analyzed {
+    /// 開始ラベル
+    pub fn entries(&self) -> &[String] {
+        return &self.entries;
+    }
+
+    /// 図を出力するディレクトリ
+    pub fn output_dir(&self) -> &Path {
+        return &self.output_dir;
+    }
+
+    pub fn analyzer(&self) -> &Analyzer {
+        return &self.analyzer;
+    }
+
+    pub fn symbols(&self) -> &SymbolTable {
+        return self.analyzer.symbols();
+    }
+
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        return self.analyzer.diagnostics();
+    }
+
+    /// 見つかった問題をソースの行と一緒に整形する
+    pub fn render_diagnostics(&self) -> Vec<String> {
+        return self.analyzer.render_diagnostics();
+    }
+
+    /// 制御フローグラフ
+    pub fn cfg(&self) -> &Cfg {
+        return self.analyzer.cfg();
+    }
+
+    /// 開始ラベルからの呼び出しグラフ
+    pub fn call_graph(&self) -> CallGraph {
+        return self.analyzer.call_graph(&self.entries);
+    }
+
+    /// json出力と同じ形の解析結果
+    pub fn analysis(&self) -> Analysis {
+        return self.analyzer.analysis(&self.entries);
+    }
+
+    /// 図を`output_dir`に出力し、書き出したファイルのパスを返す
+    ///
+    /// json以外は開始ラベルが見つからなければエラーにする
+    pub fn write(&self, graph: Graph, format: Format) -> Result<Vec<PathBuf>> {
+        if format != Format::Json {
+            self.analyzer.check_entries(&self.entries)?;
+        }
+        if self.per_entry {
+            let mut paths = vec![];
+            for entry in &self.entries {
+                let dir = self.output_dir.join(entry);
+                paths.extend(self.write_into(&dir, std::slice::from_ref(entry), graph, format)?);
+            }
+            return Ok(paths);
+        }
+        return self.write_into(&self.output_dir, &self.entries, graph, format);
+    }
+
+    fn write_into(
+        &self,
+        dir: &Path,
+        entries: &[String],
+        graph: Graph,
+        format: Format,
+    ) -> Result<Vec<PathBuf>> {
+        if self.split {
+            if graph != Graph::Cfg || format != Format::Flowchart {
+                return Err(Error::UnsupportedFormat(
+                    "only the flowchart format can be split per routine".to_string(),
+                ));
+            }
+            return self.analyzer.dump_flowchart_routines(dir, entries);
+        }
+        return Ok(vec![self.analyzer.dump(dir, entries, graph, format)?]);
+    }
+}
+
+/// 入力ファイルのあるディレクトリ(ファイル名だけなら".")
+pub fn default_output_dir(input: &Path) -> PathBuf {
+    match input.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => return dir.to_path_buf(),
+        _ => return PathBuf::from("."),
+    }
+}
+
+#[test]
+fn test_project() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/hello/hello.asm");
+    let project = Project::from_asm(path)
+        .entry("start")
+        .define("DEBUG", "1")
+        .analyze()
+        .unwrap();
+    assert_eq!(project.entries(), ["start"]);
+    assert!(project.cfg().find(None, "start").is_some());
+    assert!(project.symbols().get("DEBUG").is_some());
+    assert_eq!(project.analysis().entries, vec!["start"]);
+    assert_eq!(
+        project.output_dir(),
+        Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/hello"))
+    );
+
+    // 存在しない開始ラベル
+    let project = Project::from_asm(path).entry("nowhere").analyze().unwrap();
+    assert!(project.write(Graph::Cfg, Format::Dot).is_err());
+    assert!(Project::from_asm("missing.asm").analyze().is_err());
+    let twice = Project::from_asm(path)
+        .define("DEBUG", "1")
+        .define("DEBUG", "2");
+    assert!(matches!(twice.analyze(), Err(Error::InvalidDefinition(_))));
+
+    // 開始ラベルを指定しなければ$0100のSECTIONから探す
+    let project = Project::from_asm(path).analyze().unwrap();
+    assert_eq!(project.entries()[0], "start");
+}
+
+#[test]
+fn test_project_split() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/hello/hello.asm");
+    let dir = std::env::temp_dir().join(format!("flowchartgb_test_split_{}", std::process::id()));
+    let project = Project::from_asm(path)
+        .entry("start")
+        .output_dir(&dir)
+        .split(true)
+        .analyze()
+        .unwrap();
+    assert!(project.write(Graph::Cfg, Format::Dot).is_err());
+    project.write(Graph::Cfg, Format::Flowchart).unwrap();
+    let index = std::fs::read_to_string(dir.join("hello").join("index.md")).unwrap();
+    assert!(index.contains("- [start](hello/start.flowchart)"));
+    let chart = std::fs::read_to_string(dir.join("hello").join("hello").join("start.flowchart"));
+    assert!(chart.unwrap().contains("st->start"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_project_workspace() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/examples/picture/picture.gb");
+    // 指定しなければ一時ディレクトリに書き、捨てるときに消す
+    let project = Project::from_rom(path).analyze().unwrap();
+    let dir = project.analyzer().workspace().unwrap().to_path_buf();
+    assert!(dir.join("bank_000.asm").exists());
+    drop(project);
+    assert!(!dir.exists());
+
+    // 指定したディレクトリは残す
+    let dir =
+        std::env::temp_dir().join(format!("flowchartgb_test_workspace_{}", std::process::id()));
+    let project = Project::from_rom(path).workspace(&dir).analyze().unwrap();
+    drop(project);
+    assert!(dir.join("bank_000.asm").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_default_output_dir() {
+    assert_eq!(
+        default_output_dir(Path::new("/home/user/game/main.asm")),
+        Path::new("/home/user/game")
+    );
+    assert_eq!(default_output_dir(Path::new("main.asm")), Path::new("."));
+    assert_eq!(
+        default_output_dir(Path::new("game/main.asm")).join("main.dot"),
+        Path::new("game/main.dot")
+    );
+}
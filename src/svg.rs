@@ -0,0 +1,160 @@
+//! 制御フローグラフのSVGへの描画
+
+use super::layout::{self, Point};
+
+const CHAR_WIDTH: f64 = 7.2;
+const LINE_HEIGHT: f64 = 15.0;
+const PADDING: f64 = 6.0;
+const MARGIN: f64 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeKind {
+    Start,
+    End,
+    Block,
+    /// 条件分岐で終わるブロック
+    Conditional,
+    /// 解析したコード内に見つからない飛び先
+    Missing,
+}
+
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub kind: NodeKind,
+    /// 1行目はラベルとして太字にする
+    pub lines: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    /// 条件分岐のtrue/false
+    pub label: Option<String>,
+}
+
+/// ノードを等幅フォントの箱にして階層型に配置したSVGを作る
+pub fn render(nodes: &[Node], edges: &[Edge]) -> String {
+    let sizes: Vec<(f64, f64)> = nodes
+        .iter()
+        .map(|node| {
+            let chars = node
+                .lines
+                .iter()
+                .map(|l| l.chars().count())
+                .max()
+                .unwrap_or(0);
+            (
+                chars as f64 * CHAR_WIDTH + PADDING * 2.0,
+                node.lines.len() as f64 * LINE_HEIGHT + PADDING * 2.0,
+            )
+        })
+        .collect();
+    let pairs: Vec<(usize, usize)> = edges.iter().map(|e| (e.from, e.to)).collect();
+    let l = layout::layout(&sizes, &pairs);
+
+    let mut lines = vec![
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w:.1}\" height=\"{h:.1}\" viewBox=\"{x:.1} {x:.1} {w:.1} {h:.1}\">",
+            x = -MARGIN,
+            w = l.width + MARGIN * 2.0,
+            h = l.height + MARGIN * 2.0
+        ),
+        "<defs><marker id=\"arrow\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"8\" markerHeight=\"8\" orient=\"auto\"><path d=\"M 0 0 L 10 5 L 0 10 z\" fill=\"#444\"/></marker></defs>".to_string(),
+        "<style>text { font-family: monospace; font-size: 12px; white-space: pre; } .label { font-weight: bold; } .edge { fill: none; stroke: #444; } .true { stroke: #080; } .false { stroke: #c00; }</style>".to_string(),
+    ];
+
+    for (edge, points) in edges.iter().zip(&l.edges) {
+        let class = match &edge.label {
+            Some(label) if label.starts_with("true") => "edge true",
+            Some(_) => "edge false",
+            None => "edge",
+        };
+        lines.push(format!(
+            "<polyline class=\"{}\" points=\"{}\" marker-end=\"url(#arrow)\"/>",
+            class,
+            polyline(points)
+        ));
+        if let (Some(label), Some(&(x, y))) = (&edge.label, points.first()) {
+            lines.push(format!(
+                "<text x=\"{:.1}\" y=\"{:.1}\" font-size=\"10\">{}</text>",
+                x + 3.0,
+                y + 12.0,
+                escape(label)
+            ));
+        }
+    }
+
+    for (node, rect) in nodes.iter().zip(&l.nodes) {
+        let (fill, stroke, rx) = match node.kind {
+            NodeKind::Start | NodeKind::End => ("#eef", "#006", rect.h / 2.0),
+            NodeKind::Block => ("#fff", "#333", 2.0),
+            NodeKind::Conditional => ("#fff8e0", "#c80", 2.0),
+            NodeKind::Missing => ("#eee", "#999", 2.0),
+        };
+        lines.push(format!(
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" rx=\"{:.1}\" fill=\"{}\" stroke=\"{}\"/>",
+            rect.x, rect.y, rect.w, rect.h, rx, fill, stroke
+        ));
+        for (i, line) in node.lines.iter().enumerate() {
+            let class = if i == 0 { " class=\"label\"" } else { "" };
+            lines.push(format!(
+                "<text x=\"{:.1}\" y=\"{:.1}\"{}>{}</text>",
+                rect.x + PADDING,
+                rect.y + PADDING + LINE_HEIGHT * (i as f64 + 0.8),
+                class,
+                escape(line)
+            ));
+        }
+    }
+    lines.push("</svg>".to_string());
+    return lines.join("\n") + "\n";
+}
+
+fn polyline(points: &[Point]) -> String {
+    return points
+        .iter()
+        .map(|(x, y)| format!("{:.1},{:.1}", x, y))
+        .collect::<Vec<_>>()
+        .join(" ");
+}
+
+/// XMLのテキストとしてエスケープする
+fn escape(s: &str) -> String {
+    return s
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+}
+
+#[test]
+fn test_render_svg() {
+    let nodes = vec![
+        Node {
+            kind: NodeKind::Start,
+            lines: vec!["start".to_string()],
+        },
+        Node {
+            kind: NodeKind::Conditional,
+            lines: vec!["loop".to_string(), "cp a, <$10".to_string()],
+        },
+    ];
+    let edges = vec![
+        Edge {
+            from: 0,
+            to: 1,
+            label: None,
+        },
+        Edge {
+            from: 1,
+            to: 1,
+            label: Some("true (c)".to_string()),
+        },
+    ];
+    let svg = render(&nodes, &edges);
+    assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+    assert!(svg.contains(">cp a, &lt;$10</text>"));
+    assert!(svg.contains("class=\"edge true\""));
+    assert_eq!(svg.matches("<rect").count(), 2);
+}
@@ -0,0 +1,338 @@
+//! EQU、SET、RSSET/RB/RW、DEFなどで定義したシンボルの表
+
+use std::collections::{HashMap, HashSet};
+
+extern crate serde;
+use serde::{Deserialize, Serialize};
+
+use super::expr::{self, Env, Value};
+use super::variable::token;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    /// EQUで定義した数値
+    Constant,
+    /// SETや`=`で定義した、再定義できる数値
+    Variable,
+    /// EQUSで定義した文字列
+    String,
+    Label,
+    /// RB/RW/RLで定義したRSカウンタからのオフセット
+    RsOffset,
+}
+
+impl Default for SymbolKind {
+    fn default() -> Self {
+        return SymbolKind::Constant;
+    }
+}
+
+/// 定義した場所
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Location {
+    pub file: String,
+    /// 1始まりの行番号(逆アセンブルしたラベルなど行のない場合はNone)
+    pub line: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// 最後に定義した値(ラベルや評価できなかった場合はNone)
+    pub value: Option<Value>,
+    /// 最後に定義した式
+    pub expression: String,
+    /// 最初に定義した場所
+    pub location: Location,
+    /// 再定義した場所
+    pub redefinitions: Vec<Location>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SymbolTable {
+    symbols: HashMap<String, Symbol>,
+    /// RSカウンタ(`_RS`)
+    rs: i64,
+    /// `::`やEXPORTで公開したシンボルの名前
+    #[serde(default)]
+    exports: HashSet<String>,
+}
+
+impl SymbolTable {
+    /// シンボルを定義する行なら表に加える
+    ///
+    /// シンボルの定義でない行は`Ok(false)`、定義できない場合はエラーメッセージを返す
+    pub fn define_line(&mut self, text: &str, location: Location) -> Result<bool, String> {
+        if let Some(definition) = expr::parse_definition(text) {
+            let kind = match &definition.directive[..] {
+                token::EQU => SymbolKind::Constant,
+                token::EQUS => SymbolKind::String,
+                _ => SymbolKind::Variable,
+            };
+            let value = expr::eval_str(&definition.expression, self).ok();
+            self.define(
+                &definition.name,
+                kind,
+                value,
+                &definition.expression,
+                location,
+            )?;
+            return Ok(true);
+        }
+
+        let tokens: Vec<&str> = text
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .split_whitespace()
+            .collect();
+        let tokens = match tokens.first() {
+            Some(t) if t.eq_ignore_ascii_case(token::DEF) => &tokens[1..],
+            _ => &tokens[..],
+        };
+        let upper: Vec<String> = tokens.iter().map(|t| t.to_ascii_uppercase()).collect();
+        match upper.first().map(|t| &t[..]) {
+            Some(token::RSRESET) => {
+                self.rs = 0;
+                return Ok(true);
+            }
+            Some(token::RSSET) => {
+                let expression = tokens[1..].join(" ");
+                self.rs = expr::eval_str(&expression, self)?.as_int()?;
+                return Ok(true);
+            }
+            _ => {}
+        }
+        // NAME RB count
+        if tokens.len() >= 2 {
+            let size = match &upper[1][..] {
+                token::RB => 1,
+                token::RW => 2,
+                token::RL => 4,
+                _ => return Ok(false),
+            };
+            let count = match tokens.len() {
+                2 => 1,
+                _ => expr::eval_str(&tokens[2..].join(" "), self)?.as_int()?,
+            };
+            let name = tokens[0].trim_end_matches(':');
+            let offset = self.rs;
+            self.define(
+                name,
+                SymbolKind::RsOffset,
+                Some(Value::Int(offset)),
+                &format!("{}", offset),
+                location,
+            )?;
+            self.rs += size * count;
+            return Ok(true);
+        }
+        return Ok(false);
+    }
+
+    /// EQUSと同じ文字列のシンボルを加える(コマンドラインの`-D NAME=value`)
+    pub fn define_string(
+        &mut self,
+        name: &str,
+        value: &str,
+        location: Location,
+    ) -> Result<(), String> {
+        let expression = format!("\"{}\"", value.replace('"', "\\\""));
+        let value = Some(Value::Str(value.to_string()));
+        return self.define(name, SymbolKind::String, value, &expression, location);
+    }
+
+    /// ラベルを加える(アドレスは分からないので値はNone)
+    pub fn define_label(&mut self, name: &str, location: Location) -> Result<(), String> {
+        return self.define(name, SymbolKind::Label, None, "", location);
+    }
+
+    /// シンボルを公開する(定義より前にEXPORTしてもよい)
+    pub fn export(&mut self, name: &str) {
+        self.exports.insert(name.to_string());
+    }
+
+    /// 公開したラベルの名前順の一覧
+    pub fn exported_labels(&self) -> Vec<&Symbol> {
+        return self
+            .symbols()
+            .into_iter()
+            .filter(|s| s.kind == SymbolKind::Label && self.exports.contains(&s.name))
+            .collect();
+    }
+
+    /// SETや`=`の変数以外の再定義はエラーにする(再定義した場所は記録しておく)
+    fn define(
+        &mut self,
+        name: &str,
+        kind: SymbolKind,
+        value: Option<Value>,
+        expression: &str,
+        location: Location,
+    ) -> Result<(), String> {
+        match self.symbols.get_mut(name) {
+            Some(symbol) => {
+                symbol.redefinitions.push(location);
+                if symbol.kind != SymbolKind::Variable || kind != SymbolKind::Variable {
+                    return Err(format!(
+                        "{} is already defined at {}{}",
+                        name,
+                        symbol.location.file,
+                        symbol
+                            .location
+                            .line
+                            .map(|l| format!(":{}", l))
+                            .unwrap_or_default()
+                    ));
+                }
+                symbol.value = value;
+                symbol.expression = expression.to_string();
+            }
+            None => {
+                self.symbols.insert(
+                    name.to_string(),
+                    Symbol {
+                        name: name.to_string(),
+                        kind,
+                        value,
+                        expression: expression.to_string(),
+                        location,
+                        redefinitions: vec![],
+                    },
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    /// 後で定義されたシンボルを参照していて評価できなかった定数を評価し直す
+    pub fn resolve(&mut self) {
+        loop {
+            let mut resolved = vec![];
+            for symbol in self.symbols.values() {
+                if symbol.value.is_none() && symbol.kind != SymbolKind::Label {
+                    if let Ok(value) = expr::eval_str(&symbol.expression, self) {
+                        resolved.push((symbol.name.clone(), value));
+                    }
+                }
+            }
+            if resolved.is_empty() {
+                break;
+            }
+            for (name, value) in resolved {
+                self.symbols.get_mut(&name).unwrap().value = Some(value);
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        return self.symbols.get(name);
+    }
+
+    /// 名前順のシンボルの一覧
+    pub fn symbols(&self) -> Vec<&Symbol> {
+        let mut symbols: Vec<&Symbol> = self.symbols.values().collect();
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+        return symbols;
+    }
+
+    /// `[rLCDC]`のようにシンボルを含むオペランドなら、その値を返す
+    pub fn annotate(&self, operand: &str) -> Option<i64> {
+        let operand = operand.trim().trim_start_matches('[').trim_end_matches(']');
+        match expr::parse(operand) {
+            Ok(e) if e.has_symbol() => expr::operand_value(operand, self),
+            _ => None,
+        }
+    }
+}
+
+impl Env for SymbolTable {
+    fn symbol(&self, name: &str) -> Option<Value> {
+        if name == "_RS" {
+            return Some(Value::Int(self.rs));
+        }
+        let symbol = self.symbols.get(name)?;
+        match (&symbol.kind, &symbol.value) {
+            // EQUSの文字列が数値の式なら展開した値にする(`-D VERSION=2`など)
+            (SymbolKind::String, Some(Value::Str(s))) => match expr::parse(s) {
+                Ok(e) if !e.has_symbol() => {
+                    expr::eval(&e, self).ok().or_else(|| symbol.value.clone())
+                }
+                _ => symbol.value.clone(),
+            },
+            _ => symbol.value.clone(),
+        }
+    }
+
+    fn defined(&self, name: &str) -> bool {
+        return self.symbols.contains_key(name);
+    }
+}
+
+#[test]
+fn test_symbol_table() {
+    let mut symbols = SymbolTable::default();
+    let lines = [
+        "rLCDC EQU $FF40",
+        "TILES EQU FONT_SIZE * 2",
+        "FONT_SIZE EQU 16",
+        "counter SET 1",
+        "counter = counter + 1",
+        "counter += 3",
+        "NAME EQUS \"flowchart\"",
+        "\tRSSET $C000",
+        "wX RB 1",
+        "DEF wPos RW 2",
+        "wEnd RB",
+        "\tld a, [hl]",
+    ];
+    for (i, line) in lines.iter().enumerate() {
+        let location = Location {
+            file: "main.asm".to_string(),
+            line: Some(i + 1),
+        };
+        let defined = symbols.define_line(line, location).unwrap();
+        assert_eq!(defined, i != lines.len() - 1);
+    }
+    let start = Location {
+        file: "main.asm".to_string(),
+        line: Some(20),
+    };
+    symbols.define_label("start", start.clone()).unwrap();
+    symbols
+        .define_string("VERSION", "2", start.clone())
+        .unwrap();
+    assert!(symbols.define_label("rLCDC", start).is_err());
+    symbols.export("start");
+    symbols.export("TILES");
+    let exported: Vec<&str> = symbols
+        .exported_labels()
+        .iter()
+        .map(|s| &s.name[..])
+        .collect();
+    assert_eq!(exported, ["start"]);
+    symbols.resolve();
+
+    let value = |name: &str| symbols.get(name).and_then(|s| s.value.clone());
+    assert_eq!(value("TILES"), Some(Value::Int(32)));
+    assert_eq!(value("counter"), Some(Value::Int(5)));
+    assert_eq!(symbols.get("counter").unwrap().redefinitions.len(), 2);
+    assert_eq!(symbols.get("NAME").unwrap().kind, SymbolKind::String);
+    assert_eq!(value("wPos"), Some(Value::Int(0xC001)));
+    assert_eq!(value("wEnd"), Some(Value::Int(0xC005)));
+    assert_eq!(
+        symbols.get("rLCDC").unwrap().redefinitions[0].line,
+        Some(20)
+    );
+    assert_eq!(symbols.get("start").unwrap().kind, SymbolKind::Label);
+    assert!(symbols.defined("start"));
+    assert_eq!(symbols.symbol("VERSION"), Some(Value::Int(2)));
+    assert_eq!(
+        symbols.symbol("NAME"),
+        Some(Value::Str("flowchart".to_string()))
+    );
+    assert_eq!(symbols.annotate("[rLCDC]"), Some(0xFF40));
+    assert_eq!(symbols.annotate("$FF40"), None);
+    assert_eq!(symbols.annotate("a"), None);
+}
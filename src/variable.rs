@@ -1,6 +1,7 @@
 #[allow(dead_code)]
 pub mod token {
     pub const SECTION: &str = "SECTION";
+    pub const ENDSECTION: &str = "ENDSECTION";
 
     pub const EQU: &str = "EQU";
     pub const SET: &str = "SET";
@@ -8,18 +9,81 @@ pub mod token {
     pub const RSRESET: &str = "RSRESET";
     pub const RB: &str = "RB";
     pub const RW: &str = "RW";
+    pub const RL: &str = "RL";
     pub const EQUS: &str = "EQUS";
+    pub const DEF: &str = "DEF";
     pub const MACRO: &str = "MACRO";
     pub const ENDM: &str = "ENDM";
-    pub const MACRO_TOKEN: [&str; 8] = [
-        "IF", "ELIF", "ELSE", "ENDC", "PRINTT", "PRINTI", "PRINTV", "PRINTF",
-    ];
+    pub const IF: &str = "IF";
+    pub const ELIF: &str = "ELIF";
+    pub const ELSE: &str = "ELSE";
+    pub const ENDC: &str = "ENDC";
+    pub const SHIFT: &str = "SHIFT";
+    pub const MACRO_TOKEN: [&str; 8] =
+        [IF, ELIF, ELSE, ENDC, "PRINTT", "PRINTI", "PRINTV", "PRINTF"];
 
     pub const REPT: &str = "REPT";
+    pub const FOR: &str = "FOR";
     pub const ENDR: &str = "ENDR";
 
     pub const INCLUDE: &str = "INCLUDE";
     pub const INCBIN: &str = "INCBIN";
+
+    pub const EXPORT: &str = "EXPORT";
+    pub const GLOBAL: &str = "GLOBAL";
+
+    /// 命令の代わりに字下げして書けるディレクティブ(DB/DW/DLは`opcode::DEFINE_LIST`)
+    pub const DIRECTIVE_LIST: [&str; 49] = [
+        SECTION,
+        ENDSECTION,
+        "LOAD",
+        "ENDL",
+        "UNION",
+        "NEXTU",
+        "ENDU",
+        "PUSHS",
+        "POPS",
+        INCLUDE,
+        INCBIN,
+        EXPORT,
+        GLOBAL,
+        "PURGE",
+        "DS",
+        "ALIGN",
+        "CHARMAP",
+        "NEWCHARMAP",
+        "SETCHARMAP",
+        "PUSHC",
+        "POPC",
+        "OPT",
+        "PUSHO",
+        "POPO",
+        "PRINT",
+        "PRINTLN",
+        "PRINTT",
+        "PRINTI",
+        "PRINTV",
+        "PRINTF",
+        "WARN",
+        "FAIL",
+        "ASSERT",
+        "STATIC_ASSERT",
+        MACRO,
+        ENDM,
+        SHIFT,
+        REPT,
+        FOR,
+        ENDR,
+        "BREAK",
+        IF,
+        ELIF,
+        ELSE,
+        ENDC,
+        DEF,
+        "REDEF",
+        RSRESET,
+        RSSET,
+    ];
 }
 
 pub mod opcode {
@@ -53,6 +117,8 @@ pub mod opcode {
 
     pub const LD: &str = "LD";
     pub const LDH: &str = "LDH";
+    pub const LDI: &str = "LDI";
+    pub const LDD: &str = "LDD";
 
     pub const CALL: &str = "CALL";
     pub const JP: &str = "JP";
@@ -77,14 +143,37 @@ pub mod opcode {
     #[allow(dead_code)]
     pub const COMMENT: &str = ";";
 
+    // 別のバンクへ飛ぶマクロ
+    pub const CALLBA: &str = "CALLBA";
+    pub const FARCALL: &str = "FARCALL";
+    pub const JPBA: &str = "JPBA";
+    pub const FARJP: &str = "FARJP";
+
     pub const OPCODE_LIST: [&str; 46] = [
         ADC, ADD, AND, CP, DEC, INC, OR, SBC, SUB, XOR, BIT, RES, SET, SWAP, RL, RLA, RLC, RLCA,
-        RR, RRA, RRC, RRCA, SLA, SRA, SRL, LD, LDH, CALL, JP, JR, RET, RETI, RST, POP, PUSH, CCF,
-        CPL, DAA, DI, EI, HALT, NOP, SCF, STOP, "JPBA", "CALLBA",
+        RR, RRA, RRC, RRCA, SLA, SRA, SRL, LD, LDH, LDI, LDD, CALL, JP, JR, RET, RETI, RST, POP,
+        PUSH, CCF, CPL, DAA, DI, EI, HALT, NOP, SCF, STOP,
     ];
 
-    #[allow(dead_code)]
-    pub const JUMP_LIST: [&str; 3] = [JP, JR, CALL];
+    pub const JUMP_LIST: [&str; 2] = [JP, JR];
+
+    pub const CALL_LIST: [&str; 1] = [CALL];
+
+    /// プロジェクトで定義していなければ別のバンクへのjpとして扱うマクロ
+    pub const FAR_JUMP_LIST: [&str; 2] = [JPBA, FARJP];
+
+    /// プロジェクトで定義していなければ別のバンクへのcallとして扱うマクロ
+    pub const FAR_CALL_LIST: [&str; 2] = [CALLBA, FARCALL];
+
+    /// 飛び先を呼び出す命令か(大文字で渡す)
+    pub fn is_call(op: &str) -> bool {
+        return CALL_LIST.contains(&op) || FAR_CALL_LIST.contains(&op);
+    }
+
+    /// 飛び先へジャンプする命令か(大文字で渡す)
+    pub fn is_jump(op: &str) -> bool {
+        return JUMP_LIST.contains(&op) || FAR_JUMP_LIST.contains(&op);
+    }
 
     #[allow(dead_code)]
     pub const DEFINE_LIST: [&str; 3] = ["DB", "DW", "DL"];